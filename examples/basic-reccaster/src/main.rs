@@ -25,6 +25,24 @@ async fn main() {
     props.insert("ENGINEER".into(), "Rust Recaster".into());
     props.insert("HOSTNAME".into(), "Example-Host-Machine".into());
 
-    let mut caster = Reccaster::new(records, Some(props)).await;
-    caster.run().await;
+    let caster = match Reccaster::new(records, Some(props)).await {
+        Ok(caster) => caster,
+        Err(err) => {
+            tracing::error!("{}", err);
+            return;
+        }
+    };
+
+    // Stop cleanly on Ctrl-C
+    let handle = caster.handle();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            handle.shutdown();
+        }
+    });
+
+    match caster.run().await {
+        Ok(reason) => tracing::info!("caster stopped: {:?}", reason),
+        Err(err) => tracing::error!("{}", err),
+    }
 }
@@ -8,9 +8,12 @@
 #![allow(missing_docs)]
 use std::{collections::HashMap, sync::Arc};
 
-use pyo3::prelude::*;
+use pyo3::{
+    exceptions::{PyOSError, PyRuntimeError, PyTimeoutError, PyValueError},
+    prelude::*,
+};
 use pyo3_async_runtimes::tokio::future_into_py_with_locals;
-use reccaster::{Reccaster, Record};
+use reccaster::{Error, Reccaster, ReccasterHandle, Record};
 use tokio::sync::Mutex;
 
 #[pyclass]
@@ -73,9 +76,21 @@ impl FromPyObject<'_> for PyRecord {
     }
 }
 
+/// Map caster errors to the closest Python exception.
+fn to_pyerr(err: Error) -> PyErr {
+    match err {
+        Error::Bind { .. } | Error::Connect { .. } => PyOSError::new_err(err.to_string()),
+        Error::Validation { .. } => PyValueError::new_err(err.to_string()),
+        Error::Receive(_) => PyOSError::new_err(err.to_string()),
+        Error::PingTimeout(_) => PyTimeoutError::new_err(err.to_string()),
+        Error::Protocol(_) | Error::Closed => PyRuntimeError::new_err(err.to_string()),
+    }
+}
+
 #[pyclass]
 struct PyReccaster {
-    reccaster: Arc<Mutex<Reccaster>>,
+    reccaster: Arc<Mutex<Option<Reccaster>>>,
+    handle: ReccasterHandle,
 }
 
 #[pymethods]
@@ -92,9 +107,10 @@ impl PyReccaster {
             .map(|record: &PyRecord| record.0.clone())
             .collect::<Vec<Record>>();
         future_into_py_with_locals(py, locals, async move {
-            let recc = Reccaster::new(pvs, props).await;
+            let recc = Reccaster::new(pvs, props).await.map_err(to_pyerr)?;
             let pyrecc = PyReccaster {
-                reccaster: Arc::new(Mutex::new(recc)),
+                handle: recc.handle(),
+                reccaster: Arc::new(Mutex::new(Some(recc))),
             };
             Python::with_gil(|_py| Ok(pyrecc))
         })
@@ -104,11 +120,31 @@ impl PyReccaster {
         let recc_arc = self.reccaster.clone();
         let locals = pyo3_async_runtimes::tokio::get_current_locals(py)?;
         future_into_py_with_locals(py, locals, async move {
-            let mut recc = recc_arc.lock().await;
-            recc.run().await;
+            let recc = recc_arc
+                .lock()
+                .await
+                .take()
+                .ok_or_else(|| PyRuntimeError::new_err("the caster has already been run"))?;
+            recc.run().await.map_err(to_pyerr)?;
             Ok(())
         })
     }
+
+    fn shutdown(&self) {
+        self.handle.shutdown();
+    }
+
+    fn add_record(&self, record: PyRecord) -> PyResult<()> {
+        self.handle.add_record(record.0).map_err(to_pyerr)
+    }
+
+    fn remove_record(&self, name: String) -> PyResult<()> {
+        self.handle.remove_record(name).map_err(to_pyerr)
+    }
+
+    fn update_properties(&self, props: HashMap<String, String>) -> PyResult<()> {
+        self.handle.update_properties(props).map_err(to_pyerr)
+    }
 }
 
 #[pymodule]
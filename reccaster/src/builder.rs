@@ -0,0 +1,282 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::{Error, Reccaster, Record};
+
+/// Builder for a `Reccaster` with non-default network settings.
+///
+/// By default the caster listens for announcements on
+/// `0.0.0.0:SERVER_ANNOUNCEMENT_UDP_PORT` without address reuse, and lets the
+/// operating system pick the local address of the TCP connection.
+///
+/// To run several casters on one host, enable `reuse_address` (and
+/// `reuse_port` where available) on all of them so that each one receives
+/// the announcement broadcasts. To hear announcements from one network only,
+/// use `listen_interface` where available rather than `listen_addr`.
+#[derive(Debug, Clone)]
+pub struct ReccasterBuilder {
+    records: Vec<Record>,
+    props: Option<HashMap<String, String>>,
+    listen_addr: IpAddr,
+    listen_port: u16,
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    listen_interface: Option<String>,
+    reuse_address: bool,
+    reuse_port: bool,
+    recv_buffer_size: Option<usize>,
+    connect: ConnectOptions,
+    shutdown: CancellationToken,
+}
+
+/// Settings applied to each TCP connection to a server.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectOptions {
+    local_addr: Option<IpAddr>,
+    send_buffer_size: Option<u32>,
+    recv_buffer_size: Option<u32>,
+    keepalive: Option<Duration>,
+    ping_interval: Duration,
+    ping_timeout_factor: u32,
+}
+
+/// Default expected time between two pings from the server.
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Shortest accepted ping interval, so that the timeout is never zero.
+const MIN_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default number of ping intervals to wait before giving up on a server.
+const DEFAULT_PING_TIMEOUT_FACTOR: u32 = 4;
+
+/// Default idle time before TCP keepalive probes are sent.
+const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+impl ReccasterBuilder {
+    /// Create a builder with no records and the default settings.
+    pub fn new() -> ReccasterBuilder {
+        ReccasterBuilder {
+            records: Vec::new(),
+            props: None,
+            listen_addr: Ipv4Addr::UNSPECIFIED.into(),
+            listen_port: wire::SERVER_ANNOUNCEMENT_UDP_PORT,
+            #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+            listen_interface: None,
+            reuse_address: false,
+            reuse_port: false,
+            recv_buffer_size: None,
+            connect: ConnectOptions::default(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Records to register with the server.
+    pub fn records(mut self, records: Vec<Record>) -> Self {
+        self.records = records;
+        self
+    }
+
+    /// Client properties sent along with the records.
+    pub fn properties(mut self, props: HashMap<String, String>) -> Self {
+        self.props = Some(props);
+        self
+    }
+
+    /// Address to listen on for server announcements. Defaults to the
+    /// wildcard address.
+    ///
+    /// Announcements are broadcast, and on Linux a socket bound to a unicast
+    /// address such as that of one interface never receives broadcasts. Use
+    /// the wildcard address or the broadcast address of the subnet, and
+    /// `listen_interface` to restrict the caster to one interface.
+    pub fn listen_addr(mut self, addr: IpAddr) -> Self {
+        self.listen_addr = addr;
+        self
+    }
+
+    /// UDP port to listen on for server announcements. Defaults to
+    /// `SERVER_ANNOUNCEMENT_UDP_PORT`.
+    pub fn listen_port(mut self, port: u16) -> Self {
+        self.listen_port = port;
+        self
+    }
+
+    /// Only receive announcements arriving on the network interface called
+    /// `name`, such as `eth0`, by setting `SO_BINDTODEVICE` on the
+    /// announcement socket. Linux before 5.7 requires `CAP_NET_RAW` for it.
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    pub fn listen_interface(mut self, name: impl Into<String>) -> Self {
+        self.listen_interface = Some(name.into());
+        self
+    }
+
+    /// Set `SO_REUSEADDR` on the announcement socket.
+    pub fn reuse_address(mut self, reuse: bool) -> Self {
+        self.reuse_address = reuse;
+        self
+    }
+
+    /// Set `SO_REUSEPORT` on the announcement socket. Ignored on platforms
+    /// that do not support it.
+    pub fn reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = reuse;
+        self
+    }
+
+    /// Receive buffer size of the announcement socket, in bytes.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Local address to connect to servers from, selecting the interface
+    /// the TCP connection uses.
+    pub fn local_addr(mut self, addr: IpAddr) -> Self {
+        self.connect.local_addr = Some(addr);
+        self
+    }
+
+    /// Send buffer size of the TCP connection, in bytes.
+    pub fn tcp_send_buffer_size(mut self, size: u32) -> Self {
+        self.connect.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Receive buffer size of the TCP connection, in bytes.
+    pub fn tcp_recv_buffer_size(mut self, size: u32) -> Self {
+        self.connect.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// How often the server is expected to ping. Defaults to 15 seconds;
+    /// shorter intervals than 1 second are raised to 1 second.
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.connect.ping_interval = interval.max(MIN_PING_INTERVAL);
+        self
+    }
+
+    /// Number of ping intervals that may pass without a ping, without the
+    /// server greeting, or without it reading what the caster sends, before
+    /// the connection is considered dead and the caster goes back to waiting
+    /// for an announcement. Defaults to 4; 0 is raised to 1.
+    pub fn ping_timeout_factor(mut self, factor: u32) -> Self {
+        self.connect.ping_timeout_factor = factor.max(1);
+        self
+    }
+
+    /// Idle time before TCP keepalive probes are sent on the connection, or
+    /// `None` to disable them. Defaults to 60 seconds.
+    pub fn tcp_keepalive(mut self, time: Option<Duration>) -> Self {
+        self.connect.keepalive = time;
+        self
+    }
+
+    /// Stop the caster when `token` is cancelled, for example together with
+    /// the rest of an application. `ReccasterHandle::shutdown` cancels the
+    /// same token.
+    pub fn shutdown_token(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    /// Check the records and properties, then bind the announcement socket.
+    pub async fn build(self) -> Result<Reccaster, Error> {
+        Reccaster::validate(&self.records, &self.props)?;
+
+        let addr = SocketAddr::new(self.listen_addr, self.listen_port);
+        let udpsock = self
+            .bind_announcement(addr)
+            .map_err(|source| Error::Bind { addr, source })?;
+        debug!(
+            "listening for announcement messages at {}",
+            udpsock.local_addr().unwrap_or(addr)
+        );
+        Ok(Reccaster::from_parts(
+            udpsock,
+            self.records,
+            self.props,
+            self.connect,
+            self.shutdown,
+        ))
+    }
+
+    fn bind_announcement(&self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(self.reuse_address)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(self.reuse_port)?;
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(name) = &self.listen_interface {
+            socket.bind_device(Some(name.as_bytes()))?;
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        UdpSocket::from_std(socket.into())
+    }
+}
+
+impl Default for ReccasterBuilder {
+    fn default() -> Self {
+        ReccasterBuilder::new()
+    }
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            local_addr: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            ping_interval: DEFAULT_PING_INTERVAL,
+            ping_timeout_factor: DEFAULT_PING_TIMEOUT_FACTOR,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// How long the server may take to greet, to read what the caster
+    /// sends, or to ping once the upload is done.
+    pub(crate) fn ping_timeout(&self) -> Duration {
+        self.ping_interval.saturating_mul(self.ping_timeout_factor)
+    }
+
+    /// Open a TCP connection to `addr` with these settings.
+    pub(crate) async fn connect(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(local_addr) = self.local_addr {
+            socket.bind(SocketAddr::new(local_addr, 0))?;
+        }
+        let stream = socket.connect(addr).await?;
+        if let Some(time) = self.keepalive {
+            SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        Ok(stream)
+    }
+}
@@ -0,0 +1,87 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use std::{fmt, io, net::SocketAddr, time::Duration};
+
+/// Errors produced by the caster.
+#[derive(Debug)]
+pub enum Error {
+    /// The UDP socket for server announcements could not be bound.
+    Bind {
+        /// Address the socket was bound to.
+        addr: SocketAddr,
+        /// Underlying I/O error.
+        source: io::Error,
+    },
+    /// The TCP connection to an announced server could not be established.
+    Connect {
+        /// Address of the server.
+        addr: SocketAddr,
+        /// Underlying I/O error.
+        source: io::Error,
+    },
+    /// The connection failed or the server did not follow the protocol.
+    Protocol(wire::Error),
+    /// A record or client property cannot be sent, for example because a
+    /// string is too long for its length field.
+    Validation {
+        /// Name of the record, or `None` for a client property.
+        record: Option<String>,
+        /// Why the message could not be built.
+        source: wire::Error,
+    },
+    /// After the upload, the server did not ping, or did not take a message
+    /// the caster was sending, for this long.
+    PingTimeout(Duration),
+    /// The UDP socket for server announcements failed.
+    Receive(io::Error),
+    /// The caster behind a `ReccasterHandle` has been dropped.
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bind { addr, source } => write!(f, "cannot bind to {}: {}", addr, source),
+            Error::Connect { addr, source } => {
+                write!(f, "cannot connect to {}: {}", addr, source)
+            }
+            Error::Protocol(err) => write!(f, "protocol error: {}", err),
+            Error::Validation {
+                record: Some(name),
+                source,
+            } => write!(f, "invalid record {}: {}", name, source),
+            Error::Validation {
+                record: None,
+                source,
+            } => write!(f, "invalid client property: {}", source),
+            Error::PingTimeout(elapsed) => {
+                write!(f, "server unresponsive for {:?}", elapsed)
+            }
+            Error::Receive(err) => write!(f, "cannot receive announcements: {}", err),
+            Error::Closed => write!(f, "the caster has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Bind { source, .. } | Error::Connect { source, .. } | Error::Receive(source) => {
+                Some(source)
+            }
+            Error::Protocol(err) | Error::Validation { source: err, .. } => Some(err),
+            Error::PingTimeout(_) | Error::Closed => None,
+        }
+    }
+}
+
+impl From<wire::Error> for Error {
+    fn from(err: wire::Error) -> Self {
+        Error::Protocol(err)
+    }
+}
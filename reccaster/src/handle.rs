@@ -0,0 +1,103 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client_info_messages, record_info_messages, record_messages, CasterEvent, CasterStatus, Error,
+    Record,
+};
+
+/// Changes requested through a `ReccasterHandle`, applied by the caster in
+/// the order they were made.
+#[derive(Debug)]
+pub(crate) enum Command {
+    AddRecord(Record),
+    RemoveRecord(String),
+    UpdateProperties(HashMap<String, String>),
+}
+
+/// Cloneable handle for changing the records of a running `Reccaster`,
+/// watching its connection and stopping it.
+///
+/// While the caster is connected each change is sent to the server straight
+/// away. Changes are also kept for the next upload, so they survive a
+/// reconnect. Get one with `Reccaster::handle`.
+#[derive(Debug, Clone)]
+pub struct ReccasterHandle {
+    tx: mpsc::UnboundedSender<Command>,
+    shutdown: CancellationToken,
+    status: watch::Receiver<CasterStatus>,
+    events: broadcast::Sender<CasterEvent>,
+}
+
+impl ReccasterHandle {
+    pub(crate) fn new(
+        tx: mpsc::UnboundedSender<Command>,
+        shutdown: CancellationToken,
+        status: watch::Receiver<CasterStatus>,
+        events: broadcast::Sender<CasterEvent>,
+    ) -> ReccasterHandle {
+        ReccasterHandle {
+            tx,
+            shutdown,
+            status,
+            events,
+        }
+    }
+
+    /// Watch the connection state of the caster. The receiver sees an
+    /// error from `changed` once the caster has been dropped.
+    pub fn status(&self) -> watch::Receiver<CasterStatus> {
+        self.status.clone()
+    }
+
+    /// Subscribe to events from the caster, starting with the next one.
+    ///
+    /// A receiver that falls too far behind gets `RecvError::Lagged` and
+    /// misses the oldest events. The stream stays open while any handle
+    /// exists, so watch `status` for `Disconnected(Stopped)` to learn that
+    /// the caster has stopped.
+    pub fn events(&self) -> broadcast::Receiver<CasterEvent> {
+        self.events.subscribe()
+    }
+
+    /// Ask the caster to stop. `Reccaster::run` closes the connection to
+    /// the server, if any, and returns `StopReason::Shutdown`.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Register `record`, replacing any record with the same name.
+    ///
+    /// Fails if the record cannot be encoded or the caster has been dropped.
+    pub fn add_record(&self, record: Record) -> Result<(), Error> {
+        record_messages(0, &record)?;
+        record_info_messages(0, &record)?;
+        self.send(Command::AddRecord(record))
+    }
+
+    /// Unregister the record called `name`. Unknown names are ignored.
+    pub fn remove_record(&self, name: impl Into<String>) -> Result<(), Error> {
+        self.send(Command::RemoveRecord(name.into()))
+    }
+
+    /// Replace the client properties.
+    ///
+    /// The protocol has no way to delete a property, so a key left out of
+    /// `props` stays on the server until the caster reconnects.
+    pub fn update_properties(&self, props: HashMap<String, String>) -> Result<(), Error> {
+        client_info_messages(&props)?;
+        self.send(Command::UpdateProperties(props))
+    }
+
+    fn send(&self, cmd: Command) -> Result<(), Error> {
+        self.tx.send(cmd).map_err(|_| Error::Closed)
+    }
+}
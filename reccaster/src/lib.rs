@@ -12,29 +12,75 @@
 pub mod record;
 pub use self::record::Record;
 
+mod builder;
+use self::builder::ConnectOptions;
+pub use self::builder::ReccasterBuilder;
+
+mod error;
+pub use self::error::Error;
+
+mod handle;
+use self::handle::Command;
+pub use self::handle::ReccasterHandle;
+
+mod status;
+pub use self::status::{CasterEvent, CasterStatus, DisconnectReason};
+
 use futures::SinkExt;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::SocketAddr,
+    time::{Duration, SystemTime},
 };
 use tokio::{
     io::Interest,
     net::{TcpStream, UdpSocket},
+    sync::{broadcast, mpsc, watch},
+    time::{sleep_until, timeout, Instant},
 };
 use tokio_stream::StreamExt;
-use tokio_util::codec::Framed;
-use tracing::{debug, error, info};
-use wire::{Announcement, Message, MessageCodec, MSG_MAGIC_ID};
+use tokio_util::{codec::Framed, sync::CancellationToken};
+use tracing::{debug, info, warn};
+use wire::{Announcement, ClientCodec, ClientMessage, Message, ProtocolVersion, ServerMessage};
 
 /// An active RecSync caster that announces PV records to a RecSync server.
 pub struct Reccaster {
     udpsock: UdpSocket,
-    framed: Option<Framed<TcpStream, MessageCodec>>,
+    framed: Option<Framed<TcpStream, ClientCodec>>,
     buf: [u8; 1024],
-    pvs: Vec<Record>,
-    props: Option<HashMap<String, String>>,
+    registry: Registry,
     state: CasterState,
+    connect: ConnectOptions,
+    tx: mpsc::UnboundedSender<Command>,
+    rx: mpsc::UnboundedReceiver<Command>,
+    shutdown: CancellationToken,
+    status: watch::Sender<CasterStatus>,
+    // Sending an event fails only when nobody is subscribed, which is fine
+    events: broadcast::Sender<CasterEvent>,
+}
+
+/// Why `Reccaster::run` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The caster was asked to stop through `ReccasterHandle::shutdown` or
+    /// its shutdown token.
+    Shutdown,
+}
+
+/// Number of events kept for a slow `ReccasterHandle::events` receiver
+/// before it starts missing them.
+const EVENT_CAPACITY: usize = 64;
+
+/// How long to wait for buffered messages to reach the server when
+/// closing the connection on shutdown.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Records and client properties to upload, keyed by record identifier.
+struct Registry {
+    pvs: BTreeMap<u32, Record>,
+    props: Option<HashMap<String, String>>,
+    next_id: u32,
 }
 
 enum CasterState {
@@ -47,257 +93,513 @@ enum CasterState {
 impl Reccaster {
     /// Create a new `Reccaster` that will register `records` with optional client
     /// properties `props` once a RecSync server is discovered.
-    pub async fn new(records: Vec<Record>, props: Option<HashMap<String, String>>) -> Reccaster {
-        let sock = UdpSocket::bind(format!("0.0.0.0:{}", wire::SERVER_ANNOUNCEMENT_UDP_PORT))
-            .await
-            .unwrap();
-        debug!(
-            "listening for announcement messages at {}",
-            wire::SERVER_ANNOUNCEMENT_UDP_PORT
-        );
-        Self {
-            udpsock: sock,
+    ///
+    /// Fails if a record or property cannot be encoded, or if the UDP port
+    /// for server announcements cannot be bound. Use `Reccaster::builder`
+    /// to change the network settings.
+    pub async fn new(
+        records: Vec<Record>,
+        props: Option<HashMap<String, String>>,
+    ) -> Result<Reccaster, Error> {
+        let builder = ReccasterBuilder::new().records(records);
+        match props {
+            Some(props) => builder.properties(props),
+            None => builder,
+        }
+        .build()
+        .await
+    }
+
+    /// Start building a `Reccaster` with non-default network settings.
+    pub fn builder() -> ReccasterBuilder {
+        ReccasterBuilder::new()
+    }
+
+    /// Get a handle for adding and removing records while the caster runs.
+    pub fn handle(&self) -> ReccasterHandle {
+        ReccasterHandle::new(
+            self.tx.clone(),
+            self.shutdown.clone(),
+            self.status.subscribe(),
+            self.events.clone(),
+        )
+    }
+
+    /// Address the caster listens on for server announcements.
+    pub fn listen_addr(&self) -> io::Result<SocketAddr> {
+        self.udpsock.local_addr()
+    }
+
+    /// Reject anything that could not be uploaded before going on the network.
+    pub(crate) fn validate(
+        records: &[Record],
+        props: &Option<HashMap<String, String>>,
+    ) -> Result<(), Error> {
+        if let Some(props) = props {
+            client_info_messages(props)?;
+        }
+        for (i, record) in records.iter().enumerate() {
+            record_messages(record_id(i), record)?;
+            record_info_messages(record_id(i), record)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn from_parts(
+        udpsock: UdpSocket,
+        records: Vec<Record>,
+        props: Option<HashMap<String, String>>,
+        connect: ConnectOptions,
+        shutdown: CancellationToken,
+    ) -> Reccaster {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let next_id = record_id(records.len());
+        let pvs = records
+            .into_iter()
+            .enumerate()
+            .map(|(i, record)| (record_id(i), record))
+            .collect();
+        Reccaster {
+            udpsock,
             framed: None,
             buf: [0; 1024],
-            pvs: records,
-            props,
+            registry: Registry {
+                pvs,
+                props,
+                next_id,
+            },
             state: CasterState::Announcement,
+            connect,
+            tx,
+            rx,
+            shutdown,
+            status: watch::Sender::new(CasterStatus::Listening),
+            events: broadcast::Sender::new(EVENT_CAPACITY),
         }
     }
 
-    /// Run the caster indefinitely, cycling through discovery, handshake, upload,
-    /// and keepalive phases as the connection state changes.
-    pub async fn run(&mut self) {
+    /// Run the caster until it is shut down, cycling through discovery,
+    /// handshake, upload, and keepalive phases as the connection state changes.
+    ///
+    /// Any failure once a server has been found is logged and the caster
+    /// goes back to waiting for an announcement. Changes made through a
+    /// `ReccasterHandle` are applied as they arrive, and sent to the server
+    /// once the initial upload is done.
+    ///
+    /// On shutdown an upload in progress is abandoned, the connection is
+    /// closed after flushing the messages already queued, and the
+    /// announcement socket is released. Fails only if the announcement
+    /// socket stops working.
+    pub async fn run(mut self) -> Result<StopReason, Error> {
+        let shutdown = self.shutdown.clone();
         loop {
-            match self.state {
-                CasterState::Announcement => self.handle_announcement().await,
-                CasterState::Handshake(_) => self.handle_handshake().await,
-                CasterState::Upload => self.handle_upload().await,
-                CasterState::PingPong => self.handle_pingpong().await,
+            let result = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                result = self.step() => result,
+            };
+            match result {
+                Ok(()) => {}
+                Err(err @ Error::Receive(_)) => {
+                    self.stop().await;
+                    return Err(err);
+                }
+                Err(err) => {
+                    warn!("{}", err);
+                    if self.framed.take().is_some() {
+                        self.disconnected(DisconnectReason::from(&err));
+                    } else if let Error::Connect { addr, source } = &err {
+                        self.status
+                            .send_replace(CasterStatus::Disconnected(DisconnectReason::from(&err)));
+                        let _ = self.events.send(CasterEvent::ConnectFailed {
+                            server: *addr,
+                            reason: source.kind(),
+                        });
+                    }
+                    self.state = CasterState::Announcement;
+                }
             }
         }
+        info!("Shutting down");
+        self.stop().await;
+        Ok(StopReason::Shutdown)
+    }
+
+    async fn step(&mut self) -> Result<(), Error> {
+        match self.state {
+            CasterState::Announcement => self.handle_announcement().await,
+            CasterState::Handshake(_) => self.handle_handshake().await,
+            CasterState::Upload => self.handle_upload().await,
+            CasterState::PingPong => self.handle_pingpong().await,
+        }
+    }
+
+    fn disconnected(&self, reason: DisconnectReason) {
+        self.status
+            .send_replace(CasterStatus::Disconnected(reason.clone()));
+        let _ = self.events.send(CasterEvent::ConnectionLost(reason));
     }
 
-    async fn handle_announcement(&mut self) {
-        let ready = match self.udpsock.ready(Interest::READABLE).await {
-            Ok(ready) => ready,
-            Err(_) => {
-                self.state = CasterState::Announcement;
-                return;
+    /// Close the connection to the server, if any, in an orderly way.
+    async fn stop(&mut self) {
+        if self.framed.is_none() {
+            self.status
+                .send_replace(CasterStatus::Disconnected(DisconnectReason::Stopped));
+        } else {
+            self.disconnected(DisconnectReason::Stopped);
+        }
+        if let Some(mut framed) = self.framed.take() {
+            match timeout(CLOSE_TIMEOUT, framed.close()).await {
+                Ok(Ok(())) => debug!("Closed connection"),
+                Ok(Err(err)) => debug!("Error closing connection: {}", err),
+                Err(_) => debug!("Timed out closing connection"),
+            }
+        }
+    }
+
+    async fn handle_announcement(&mut self) -> Result<(), Error> {
+        let ready = tokio::select! {
+            ready = self.udpsock.ready(Interest::READABLE) => ready,
+            Some(cmd) = self.rx.recv() => {
+                // Not connected, so only the next upload sees the change
+                self.registry.apply(cmd);
+                return Ok(());
             }
         };
+        let ready = ready.map_err(Error::Receive)?;
         if ready.is_readable() {
             match self.udpsock.try_recv_from(&mut self.buf) {
                 Ok((len, addr)) => {
-                    if len >= 16 {
-                        match Self::parse_announcement_message(&self.buf[..len], addr) {
-                            Ok(msg) => {
-                                info!("Received announcement message: {:?}:{:?} with key:{:?} from: {:?}", msg.server_addr, msg.server_port, msg.server_key, addr);
-                                self.state = CasterState::Handshake(msg);
-                            }
-                            Err(_) => {
-                                debug!("Invalid announcement message");
-                                self.state = CasterState::Announcement;
-                            }
-                        };
-                    }
+                    match Announcement::decode(&self.buf[..len], addr) {
+                        Ok(msg) => {
+                            info!(
+                                "Received announcement message: {:?}:{:?} with key:{:?} from: {:?}",
+                                msg.server_addr, msg.server_port, msg.server_key, addr
+                            );
+                            let server = SocketAddr::from((msg.server_addr, msg.server_port));
+                            self.status.send_replace(CasterStatus::Connecting(server));
+                            let _ = self.events.send(CasterEvent::AnnouncementSeen(msg.clone()));
+                            self.state = CasterState::Handshake(msg);
+                        }
+                        Err(err) => {
+                            debug!("Invalid announcement message: {}", err);
+                            self.state = CasterState::Announcement;
+                        }
+                    };
                 }
                 Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
-                Err(err) => {
-                    error!("{:?}", err)
-                }
+                Err(err) => return Err(Error::Receive(err)),
             };
         }
+        Ok(())
     }
 
-    async fn handle_handshake(&mut self) {
+    async fn handle_handshake(&mut self) -> Result<(), Error> {
         if let CasterState::Handshake(msg) = &mut self.state {
-            let addr = msg.server_addr;
-            let port = msg.server_port;
+            let addr = SocketAddr::from((msg.server_addr, msg.server_port));
             let key = msg.server_key;
-            let stream = match TcpStream::connect(format!("{}:{}", addr, port)).await {
-                Ok(stream) => {
-                    info!("Connected to {:?}:{}", addr, port);
-                    stream
-                }
-                Err(_) => {
-                    debug!("Connection failed {:?}:{}", addr, port);
-                    self.state = CasterState::Announcement;
-                    return;
-                }
-            };
-            let codec = MessageCodec;
-            let framed = Framed::new(stream, codec);
-            self.framed = Some(framed);
+            let stream = self
+                .connect
+                .connect(addr)
+                .await
+                .map_err(|source| Error::Connect { addr, source })?;
+            info!("Connected to {}", addr);
+            let codec = ClientCodec::default();
+            let framed = self.framed.insert(Framed::new(stream, codec));
 
-            if let Some(framed) = &mut self.framed {
-                let msg = match framed.next().await {
-                    Some(Ok(msg)) => msg,
-                    _ => {
-                        self.state = CasterState::Announcement;
-                        return;
-                    }
-                };
-                match msg {
-                    Message::ServerGreet(_) => {
-                        let _ = framed
-                            .send(Message::ClientGreet(wire::ClientGreet { serv_key: key }))
-                            .await;
-                        debug!("Greet Message with server key: {}", key);
-                        self.state = CasterState::Upload;
-                    }
-                    _ => {
-                        self.state = CasterState::Announcement;
-                    }
+            // A server that accepts but never greets is as dead as one that
+            // stops pinging
+            let ping_timeout = self.connect.ping_timeout();
+            let greet = timeout(ping_timeout, next_message(framed))
+                .await
+                .map_err(|_| Error::PingTimeout(ping_timeout))??;
+            match greet {
+                ServerMessage::ServerGreet(greet) => {
+                    // Speak the highest version both sides support
+                    let version = ProtocolVersion::CURRENT.negotiate(greet.version);
+                    let msg = ClientMessage::ClientGreet(wire::ClientGreet {
+                        version,
+                        serv_key: key,
+                    });
+                    send_within(framed, msg, ping_timeout).await?;
+                    debug!(
+                        "Greet Message with server key: {}, protocol version {} (server supports {})",
+                        key, version, greet.version
+                    );
+                    let _ = self.events.send(CasterEvent::HandshakeDone {
+                        server: addr,
+                        version,
+                    });
+                    self.state = CasterState::Upload;
                 }
+                msg => return Err(unexpected(msg)),
             }
         }
+        Ok(())
     }
 
-    async fn handle_upload(&mut self) {
+    async fn handle_upload(&mut self) -> Result<(), Error> {
         if let CasterState::Upload = &mut self.state {
             if let Some(framed) = &mut self.framed {
-                for (i, record) in self.pvs.iter().enumerate() {
-                    let recid: u32 = i as u32 + 100;
-                    // AddRecord Message
-                    let record_name = &record.name;
-                    let record_type = &record.r#type;
-                    let msg = Message::AddRecord(wire::AddRecord {
-                        recid,
-                        atype: wire::AddRecordType::Record as u8,
-                        rtlen: record_type.len() as u8,
-                        rnlen: record_name.len() as u16,
-                        rtype: record_type.to_string(),
-                        rname: record_name.to_string(),
-                    });
-                    let _ = framed.send(msg.clone()).await;
-                    debug!("Sending AddRecord Message: {:?}", msg);
-                    // AddRecord alias Message if avaliable
-                    if let Some(record_alias) = &record.alias {
-                        let msg = Message::AddRecord(wire::AddRecord {
-                            recid,
-                            atype: wire::AddRecordType::Alias as u8,
-                            rtlen: record_type.len() as u8,
-                            rnlen: record_alias.len() as u16,
-                            rtype: record_type.to_string(),
-                            rname: record_alias.to_string(),
-                        });
-                        let _ = framed.send(msg.clone()).await;
-                    };
-                    // AddInfo Message
+                let ping_timeout = self.connect.ping_timeout();
+                let total = self.registry.pvs.len();
+                self.status
+                    .send_replace(CasterStatus::Uploading { sent: 0, total });
+                for (sent, (&recid, record)) in self.registry.pvs.iter().enumerate() {
+                    // AddRecord Message, and AddRecord alias Message if avaliable
+                    let mut msgs = record_messages(recid, record)?;
                     // Send Client Properties
-                    if let Some(props) = &self.props {
-                        for (key, value) in props {
-                            let msg: Message = Message::AddInfo(wire::AddInfo {
-                                recid: 0,
-                                keylen: key.len() as u8,
-                                valen: value.len() as u16,
-                                key: key.to_string(),
-                                value: value.to_string(),
-                            });
-                            let _ = framed.send(msg.clone()).await;
-                            debug!("Sending AddInfo Message: {:?}", msg.clone());
-                        }
+                    if let Some(props) = &self.registry.props {
+                        msgs.extend(client_info_messages(props)?);
                     }
                     // Send Record Properties
-                    for (key, value) in &record.properties {
-                        let msg = Message::AddInfo(wire::AddInfo {
-                            recid,
-                            keylen: key.len() as u8,
-                            valen: value.len() as u16,
-                            key: key.to_string(),
-                            value: value.to_string(),
-                        });
-                        let _ = framed.send(msg.clone()).await;
-                        debug!("Sending AddInfo Message: {:?}", msg.clone());
+                    msgs.extend(record_info_messages(recid, record)?);
+                    for msg in msgs {
+                        debug!("Sending {}", msg);
+                        send_within(framed, msg, ping_timeout).await?;
                     }
+                    self.status.send_replace(CasterStatus::Uploading {
+                        sent: sent + 1,
+                        total,
+                    });
                 }
-                let _ = framed.send(Message::UploadDone(wire::UploadDone)).await;
+                send_within(
+                    framed,
+                    ClientMessage::UploadDone(wire::UploadDone),
+                    ping_timeout,
+                )
+                .await?;
                 debug!("Sending UploadDone Message");
+                self.status.send_replace(CasterStatus::Registered {
+                    since: SystemTime::now(),
+                    last_ping: None,
+                });
+                let _ = self
+                    .events
+                    .send(CasterEvent::UploadComplete { records: total });
                 self.state = CasterState::PingPong;
             }
         }
+        Ok(())
     }
 
-    async fn handle_pingpong(&mut self) {
+    async fn handle_pingpong(&mut self) -> Result<(), Error> {
         if let CasterState::PingPong = &mut self.state {
             if let Some(framed) = &mut self.framed {
-                while let Some(msg_result) = framed.next().await {
-                    match msg_result {
-                        Ok(msg) => match msg {
-                            Message::Ping(ping_msg) => {
-                                info!("received ping with nonce: {}", ping_msg.nonce);
-                                if framed
-                                    .send(Message::Pong(wire::Pong {
-                                        nonce: ping_msg.nonce,
-                                    }))
-                                    .await
-                                    .is_err()
-                                {
-                                    self.state = CasterState::Announcement;
-                                    return;
-                                }
+                // A host that vanishes without a reset would otherwise
+                // leave the caster waiting here, or blocked in a send,
+                // forever
+                let ping_timeout = self.connect.ping_timeout();
+                let mut deadline = Instant::now() + ping_timeout;
+                loop {
+                    let msg = tokio::select! {
+                        msg = next_message(framed) => msg?,
+                        _ = sleep_until(deadline) => return Err(Error::PingTimeout(ping_timeout)),
+                        Some(cmd) = self.rx.recv() => {
+                            for msg in self.registry.apply(cmd) {
+                                debug!("Sending {}", msg);
+                                send_within(framed, msg, ping_timeout).await?;
                             }
-                            _ => {
-                                self.state = CasterState::Announcement;
-                                return;
-                            }
-                        },
-                        Err(_) => {
-                            self.state = CasterState::Announcement;
-                            return;
+                            continue;
                         }
+                    };
+                    match msg {
+                        ServerMessage::Ping(ping_msg) => {
+                            info!("received ping with nonce: {}", ping_msg.nonce);
+                            deadline = Instant::now() + ping_timeout;
+                            let pong = ClientMessage::Pong(wire::Pong {
+                                nonce: ping_msg.nonce,
+                            });
+                            send_within(framed, pong, ping_timeout).await?;
+                            self.status.send_modify(|status| {
+                                if let CasterStatus::Registered { last_ping, .. } = status {
+                                    *last_ping = Some(SystemTime::now());
+                                }
+                            });
+                            let _ = self.events.send(CasterEvent::PingAnswered {
+                                nonce: ping_msg.nonce,
+                            });
+                        }
+                        ServerMessage::Unknown { msg_id, .. } => {
+                            debug!("ignoring unknown message id {:#06x}", msg_id);
+                        }
+                        msg => return Err(unexpected(msg)),
                     }
                 }
-                self.state = CasterState::Announcement;
             }
         }
+        Ok(())
     }
+}
 
-    fn parse_announcement_message(
-        data: &[u8],
-        src_addr: SocketAddr,
-    ) -> Result<Announcement, &'static str> {
-        let id = u16::from_be_bytes([data[0], data[1]]);
-        // Checking if the ID is 'RC'
-        if id != MSG_MAGIC_ID {
-            return Err("Invalid ID");
-        }
-
-        let version = data[2];
-        if version != 0 {
-            return Err("Invalid version");
-        }
-
-        // Extracting the server address (IPv4, 4 bytes)
-        let server_addr_bytes = &data[4..8];
-        let mut server_addr = Ipv4Addr::new(
-            server_addr_bytes[0],
-            server_addr_bytes[1],
-            server_addr_bytes[2],
-            server_addr_bytes[3],
-        );
-
-        if server_addr.is_broadcast() {
-            match src_addr.ip() {
-                IpAddr::V4(addr) => {
-                    server_addr = addr;
+impl Registry {
+    /// Apply `cmd`, returning the messages that bring a connected server up
+    /// to date.
+    ///
+    /// A command that cannot be encoded is logged and ignored, so that it
+    /// never costs the caster its connection.
+    fn apply(&mut self, cmd: Command) -> Vec<ClientMessage> {
+        match cmd {
+            Command::AddRecord(record) => {
+                // Take the identifier before removing an older record of the
+                // same name, so that the server never sees one reused
+                let recid = self.allocate_id();
+                let added = record_messages(recid, &record).and_then(|mut msgs| {
+                    msgs.extend(record_info_messages(recid, &record)?);
+                    Ok(msgs)
+                });
+                let added = match added {
+                    Ok(msgs) => msgs,
+                    Err(err) => {
+                        warn!("Ignoring {}", err);
+                        return Vec::new();
+                    }
+                };
+                let mut msgs: Vec<_> = self.remove(&record.name).into_iter().collect();
+                msgs.extend(added);
+                self.pvs.insert(recid, record);
+                msgs
+            }
+            Command::RemoveRecord(name) => {
+                let msg = self.remove(&name);
+                if msg.is_none() {
+                    debug!("no record named {} to remove", name);
                 }
-                IpAddr::V6(_) => {
-                    unimplemented!("IPv6 is not supported")
+                msg.into_iter().collect()
+            }
+            Command::UpdateProperties(props) => match client_info_messages(&props) {
+                Ok(msgs) => {
+                    self.props = Some(props);
+                    msgs
+                }
+                Err(err) => {
+                    warn!("Ignoring {}", err);
+                    Vec::new()
                 }
+            },
+        }
+    }
+
+    /// Take the next free record identifier. Identifiers wrap around to
+    /// `FIRST_RECORD_ID` instead of overflowing, skipping any still in use.
+    fn allocate_id(&mut self) -> u32 {
+        loop {
+            let recid = self.next_id;
+            self.next_id = recid.checked_add(1).unwrap_or(FIRST_RECORD_ID);
+            if !self.pvs.contains_key(&recid) {
+                return recid;
             }
         }
+    }
+
+    /// Forget the record called `name`, returning the message that deletes
+    /// it from the server if there was one.
+    fn remove(&mut self, name: &str) -> Option<ClientMessage> {
+        let recid = self
+            .pvs
+            .iter()
+            .find(|(_, record)| record.name == name)
+            .map(|(&recid, _)| recid)?;
+        self.pvs.remove(&recid);
+        Some(ClientMessage::DelRecord(wire::DelRecord { recid }))
+    }
+}
+
+/// Identifier of the first record. Lower identifiers are reserved, with 0
+/// used for the client properties.
+const FIRST_RECORD_ID: u32 = 100;
+
+/// Record identifier used for the record at `index`.
+fn record_id(index: usize) -> u32 {
+    index as u32 + FIRST_RECORD_ID
+}
 
-        let server_port = u16::from_be_bytes([data[8], data[9]]);
+/// Messages registering `record` and its alias if it has one.
+fn record_messages(recid: u32, record: &Record) -> Result<Vec<ClientMessage>, Error> {
+    let invalid = |source| invalid_record(record, source);
+    let mut msgs = vec![ClientMessage::AddRecord(
+        wire::AddRecord::new(
+            recid,
+            wire::AddRecordType::Record,
+            record.r#type.clone(),
+            record.name.clone(),
+        )
+        .map_err(invalid)?,
+    )];
+    if let Some(alias) = &record.alias {
+        msgs.push(ClientMessage::AddRecord(
+            wire::AddRecord::new(
+                recid,
+                wire::AddRecordType::Alias,
+                record.r#type.clone(),
+                alias.clone(),
+            )
+            .map_err(invalid)?,
+        ));
+    }
+    Ok(msgs)
+}
 
-        let server_key = u32::from_be_bytes([data[12], data[13], data[14], data[15]]);
+/// Messages sending the properties of `record`.
+fn record_info_messages(recid: u32, record: &Record) -> Result<Vec<ClientMessage>, Error> {
+    record
+        .properties
+        .iter()
+        .map(|(key, value)| {
+            wire::AddInfo::new(recid, key.clone(), value.clone())
+                .map(ClientMessage::AddInfo)
+                .map_err(|source| invalid_record(record, source))
+        })
+        .collect()
+}
+
+fn invalid_record(record: &Record, source: wire::Error) -> Error {
+    Error::Validation {
+        record: Some(record.name.clone()),
+        source,
+    }
+}
 
-        Ok(Announcement {
-            id,
-            server_addr,
-            server_port,
-            server_key,
+/// Messages sending the client properties `props`.
+fn client_info_messages(props: &HashMap<String, String>) -> Result<Vec<ClientMessage>, Error> {
+    props
+        .iter()
+        .map(|(key, value)| {
+            wire::AddInfo::new(0, key.clone(), value.clone())
+                .map(ClientMessage::AddInfo)
+                .map_err(|source| Error::Validation {
+                    record: None,
+                    source,
+                })
         })
+        .collect()
+}
+
+/// Wait for the next message from the server. The server closing the
+/// connection is reported as an error.
+async fn next_message(framed: &mut Framed<TcpStream, ClientCodec>) -> Result<ServerMessage, Error> {
+    match framed.next().await {
+        Some(msg) => Ok(msg?),
+        None => Err(Error::Protocol(wire::Error::Io(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "server closed the connection",
+        )))),
     }
 }
+
+/// Send `msg`, giving up with `Error::PingTimeout` if the server has not
+/// taken it within `limit`.
+async fn send_within(
+    framed: &mut Framed<TcpStream, ClientCodec>,
+    msg: ClientMessage,
+    limit: Duration,
+) -> Result<(), Error> {
+    match timeout(limit, framed.send(msg)).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(Error::PingTimeout(limit)),
+    }
+}
+
+fn unexpected(msg: ServerMessage) -> Error {
+    Error::Protocol(wire::Error::UnexpectedMessage(Message::from(msg).msg_id()))
+}
@@ -0,0 +1,116 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use std::{fmt, io, net::SocketAddr, time::SystemTime};
+use wire::{Announcement, ProtocolVersion};
+
+use crate::Error;
+
+/// Connection state of a caster, published through
+/// `ReccasterHandle::status`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CasterStatus {
+    /// Waiting for the first server announcement.
+    Listening,
+    /// Connecting to, and greeting, the announced server at this address.
+    Connecting(SocketAddr),
+    /// Uploading the records to the server.
+    Uploading {
+        /// Records sent so far.
+        sent: usize,
+        /// Records in the upload.
+        total: usize,
+    },
+    /// The upload is done and the server holds the records.
+    Registered {
+        /// When the upload finished.
+        since: SystemTime,
+        /// When the last ping from the server was answered.
+        last_ping: Option<SystemTime>,
+    },
+    /// The last connection ended and the caster is waiting for another
+    /// announcement, or has stopped.
+    Disconnected(DisconnectReason),
+}
+
+/// Why a connection to a server ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The TCP connection could not be established.
+    ConnectFailed(io::ErrorKind),
+    /// The server closed the connection.
+    Closed,
+    /// The connection failed with an I/O error.
+    Io(io::ErrorKind),
+    /// The server stopped pinging, or stopped reading.
+    PingTimeout,
+    /// The server did not follow the protocol.
+    Protocol(String),
+    /// The caster was shut down.
+    Stopped,
+}
+
+/// Something that happened to a caster, published through
+/// `ReccasterHandle::events`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CasterEvent {
+    /// A valid server announcement was received.
+    AnnouncementSeen(Announcement),
+    /// The greetings were exchanged with a server.
+    HandshakeDone {
+        /// Address of the server.
+        server: SocketAddr,
+        /// Protocol version spoken on the connection.
+        version: ProtocolVersion,
+    },
+    /// All records were sent, followed by `UploadDone`.
+    UploadComplete {
+        /// Number of records uploaded.
+        records: usize,
+    },
+    /// A ping from the server was answered.
+    PingAnswered {
+        /// Nonce of the ping.
+        nonce: u32,
+    },
+    /// An announced server could not be connected to.
+    ConnectFailed {
+        /// Address of the server.
+        server: SocketAddr,
+        /// Why the connection failed.
+        reason: io::ErrorKind,
+    },
+    /// The connection to the server ended.
+    ConnectionLost(DisconnectReason),
+}
+
+impl From<&Error> for DisconnectReason {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Connect { source, .. } => DisconnectReason::ConnectFailed(source.kind()),
+            Error::Protocol(wire::Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                DisconnectReason::Closed
+            }
+            Error::Protocol(wire::Error::Io(err)) => DisconnectReason::Io(err.kind()),
+            Error::PingTimeout(_) => DisconnectReason::PingTimeout,
+            err => DisconnectReason::Protocol(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::ConnectFailed(kind) => write!(f, "cannot connect: {}", kind),
+            DisconnectReason::Closed => write!(f, "closed by the server"),
+            DisconnectReason::Io(kind) => write!(f, "I/O error: {}", kind),
+            DisconnectReason::PingTimeout => write!(f, "server unresponsive"),
+            DisconnectReason::Protocol(err) => write!(f, "{}", err),
+            DisconnectReason::Stopped => write!(f, "caster stopped"),
+        }
+    }
+}
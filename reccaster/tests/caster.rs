@@ -0,0 +1,704 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use futures::{SinkExt, StreamExt};
+use reccaster::{
+    CasterEvent, CasterStatus, DisconnectReason, Error, Reccaster, Record, StopReason,
+};
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::broadcast,
+    time::{sleep, Instant},
+};
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+use wire::{Announcement, Ping, ProtocolVersion, ServerCodec, ServerGreet, ServerMessage};
+
+#[tokio::test]
+async fn oversize_record_is_rejected_before_binding() {
+    let mut record = Record::new("DEV:AI".into(), "ai".into());
+    record.properties.insert("k".repeat(300), "v".into());
+    match Reccaster::new(vec![record], None).await {
+        Err(Error::Validation {
+            record: Some(name),
+            source: wire::Error::FieldTooLong { field: "key", .. },
+        }) => assert_eq!(name, "DEV:AI"),
+        Err(err) => panic!("unexpected error {}", err),
+        Ok(_) => panic!("oversize property accepted"),
+    }
+
+    let props = HashMap::from([("ENGINEER".to_string(), "x".repeat(70_000))]);
+    assert!(matches!(
+        Reccaster::new(Vec::new(), Some(props)).await,
+        Err(Error::Validation { record: None, .. })
+    ));
+}
+
+#[tokio::test]
+async fn casters_share_the_announcement_port_with_reuse() {
+    let first = Reccaster::builder()
+        .listen_port(0)
+        .reuse_address(true)
+        .reuse_port(true)
+        .build()
+        .await
+        .unwrap();
+    let port = first.listen_addr().unwrap().port();
+
+    let second = Reccaster::builder()
+        .listen_port(port)
+        .reuse_address(true)
+        .reuse_port(true)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(second.listen_addr().unwrap().port(), port);
+
+    let third = Reccaster::builder().listen_port(port).build().await;
+    assert!(matches!(third, Err(Error::Bind { .. })));
+
+    // Both casters hear a broadcast announcement
+    let mut casters = Vec::new();
+    for caster in [first, second] {
+        let handle = caster.handle();
+        let events = handle.events();
+        casters.push((handle, events, tokio::spawn(caster.run())));
+    }
+    broadcast_announcement(port).await;
+    for (handle, mut events, task) in casters {
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            CasterEvent::AnnouncementSeen(_)
+        ));
+        handle.shutdown();
+        task.await.unwrap().unwrap();
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn listen_interface_receives_broadcasts_on_that_interface() {
+    let caster = Reccaster::builder()
+        .listen_port(0)
+        .listen_interface("lo")
+        .build()
+        .await
+        .unwrap();
+    let port = caster.listen_addr().unwrap().port();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    broadcast_announcement(port).await;
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        CasterEvent::AnnouncementSeen(_)
+    ));
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+
+    let missing = Reccaster::builder()
+        .listen_port(0)
+        .listen_interface("nosuchif0")
+        .build()
+        .await;
+    assert!(matches!(missing, Err(Error::Bind { .. })));
+}
+
+/// Broadcast an announcement for a server that is not listening to the
+/// loopback network on `port`.
+async fn broadcast_announcement(port: u16) {
+    let announcement = Announcement::new(Ipv4Addr::LOCALHOST, 1, 0xcafe);
+    let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    udp.set_broadcast(true).unwrap();
+    udp.send_to(
+        &announcement.encode(),
+        (Ipv4Addr::new(127, 255, 255, 255), port),
+    )
+    .await
+    .unwrap();
+}
+
+/// Tell the caster listening at `caster` about a server on `port`.
+async fn announce(caster: SocketAddr, port: u16) {
+    let announcement = Announcement::new(Ipv4Addr::LOCALHOST, port, 0xcafe);
+    let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    udp.send_to(&announcement.encode(), caster).await.unwrap();
+}
+
+/// Accept a connection from the caster, greet it and collect its upload.
+async fn accept_upload(
+    listener: &TcpListener,
+) -> (Framed<TcpStream, ServerCodec>, SocketAddr, Vec<String>) {
+    let (stream, peer) = listener.accept().await.unwrap();
+    let mut framed = Framed::new(stream, ServerCodec::default());
+    framed
+        .send(ServerMessage::ServerGreet(ServerGreet::default()))
+        .await
+        .unwrap();
+
+    let mut received = Vec::new();
+    loop {
+        let msg = recv(&mut framed).await;
+        let done = msg == "UploadDone";
+        received.push(msg);
+        if done {
+            return (framed, peer, received);
+        }
+    }
+}
+
+async fn recv(framed: &mut Framed<TcpStream, ServerCodec>) -> String {
+    framed.next().await.unwrap().unwrap().to_string()
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn uploads_from_the_configured_local_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let mut record = Record::new("DEV:AI".into(), "ai".into());
+    record.alias = Some("DEV:ALIAS".into());
+    let caster = Reccaster::builder()
+        .records(vec![record])
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .local_addr(Ipv4Addr::new(127, 0, 0, 2).into())
+        .tcp_send_buffer_size(64 * 1024)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let task = tokio::spawn(async move { caster.run().await });
+
+    announce(listen_addr, listener.local_addr().unwrap().port()).await;
+    let (_framed, peer, received) = accept_upload(&listener).await;
+    assert_eq!(peer.ip(), Ipv4Addr::new(127, 0, 0, 2));
+    assert_eq!(
+        received,
+        [
+            "ClientGreet version=0 key=0x0000cafe",
+            "AddRecord recid=100 record type=ai name=DEV:AI",
+            "AddRecord recid=100 alias type=ai name=DEV:ALIAS",
+            "UploadDone",
+        ]
+    );
+    task.abort();
+}
+
+#[tokio::test]
+async fn handle_changes_are_sent_and_kept_for_the_next_upload() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = listener.local_addr().unwrap().port();
+
+    let props = HashMap::from([("ENGINEER".to_string(), "a".to_string())]);
+    let caster = Reccaster::builder()
+        .records(vec![Record::new("DEV:A".into(), "ai".into())])
+        .properties(props)
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let task = tokio::spawn(async move { caster.run().await });
+
+    announce(listen_addr, server_port).await;
+    let (mut framed, _, received) = accept_upload(&listener).await;
+    assert_eq!(
+        received,
+        [
+            "ClientGreet version=0 key=0x0000cafe",
+            "AddRecord recid=100 record type=ai name=DEV:A",
+            "AddInfo recid=0 key=\"ENGINEER\" value=\"a\"",
+            "UploadDone",
+        ]
+    );
+
+    let mut record = Record::new("DEV:B".into(), "bo".into());
+    record.properties.insert("recordDesc".into(), "b".into());
+    handle.add_record(record).unwrap();
+    assert_eq!(
+        recv(&mut framed).await,
+        "AddRecord recid=101 record type=bo name=DEV:B"
+    );
+    assert_eq!(
+        recv(&mut framed).await,
+        "AddInfo recid=101 key=\"recordDesc\" value=\"b\""
+    );
+    handle.remove_record("DEV:A").unwrap();
+    handle.remove_record("DEV:NONE").unwrap();
+    assert_eq!(recv(&mut framed).await, "DelRecord recid=100");
+    let props = HashMap::from([("ENGINEER".to_string(), "b".to_string())]);
+    handle.update_properties(props).unwrap();
+    assert_eq!(
+        recv(&mut framed).await,
+        "AddInfo recid=0 key=\"ENGINEER\" value=\"b\""
+    );
+
+    // After a reconnect the server gets the updated set
+    drop(framed);
+    announce(listen_addr, server_port).await;
+    let (_framed, _, received) = accept_upload(&listener).await;
+    assert_eq!(
+        received,
+        [
+            "ClientGreet version=0 key=0x0000cafe",
+            "AddRecord recid=101 record type=bo name=DEV:B",
+            "AddInfo recid=0 key=\"ENGINEER\" value=\"b\"",
+            "AddInfo recid=101 key=\"recordDesc\" value=\"b\"",
+            "UploadDone",
+        ]
+    );
+
+    task.abort();
+    let _ = task.await;
+    assert!(matches!(handle.remove_record("DEV:B"), Err(Error::Closed)));
+}
+
+#[tokio::test]
+async fn handle_rejects_invalid_records() {
+    let caster = Reccaster::builder()
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .build()
+        .await
+        .unwrap();
+    let record = Record::new("x".repeat(70_000), "ai".into());
+    assert!(matches!(
+        caster.handle().add_record(record),
+        Err(Error::Validation {
+            record: Some(_),
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn shutdown_closes_the_connection_and_releases_the_port() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let caster = Reccaster::builder()
+        .records(vec![Record::new("DEV:A".into(), "ai".into())])
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, listener.local_addr().unwrap().port()).await;
+    let (mut framed, _, received) = accept_upload(&listener).await;
+    assert_eq!(received.last().unwrap(), "UploadDone");
+
+    handle.shutdown();
+    assert_eq!(task.await.unwrap().unwrap(), StopReason::Shutdown);
+    // An orderly close, not a reset
+    assert!(framed.next().await.is_none());
+    // The announcement port can be bound again without address reuse
+    UdpSocket::bind(listen_addr).await.unwrap();
+    assert!(matches!(
+        handle.add_record(Record::new("DEV:B".into(), "ai".into())),
+        Err(Error::Closed)
+    ));
+}
+
+#[tokio::test]
+async fn shutdown_token_stops_an_idle_caster() {
+    let token = CancellationToken::new();
+    let caster = Reccaster::builder()
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .shutdown_token(token.clone())
+        .build()
+        .await
+        .unwrap();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    token.cancel();
+    assert_eq!(task.await.unwrap().unwrap(), StopReason::Shutdown);
+    // No connection was ever made, so none was lost
+    assert!(events.try_recv().is_err());
+    assert_eq!(
+        *handle.status().borrow(),
+        CasterStatus::Disconnected(DisconnectReason::Stopped)
+    );
+}
+
+#[tokio::test]
+async fn status_and_events_follow_the_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap();
+
+    let caster = Reccaster::builder()
+        .records(vec![
+            Record::new("DEV:A".into(), "ai".into()),
+            Record::new("DEV:B".into(), "ai".into()),
+        ])
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut status = handle.status();
+    let mut events = handle.events();
+    assert_eq!(*status.borrow(), CasterStatus::Listening);
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, server.port()).await;
+    let (mut framed, _, _) = accept_upload(&listener).await;
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        CasterEvent::AnnouncementSeen(Announcement { server_port, .. }) if server_port == server.port()
+    ));
+    assert_eq!(
+        events.recv().await.unwrap(),
+        CasterEvent::HandshakeDone {
+            server,
+            version: ProtocolVersion::V0
+        }
+    );
+    assert_eq!(
+        events.recv().await.unwrap(),
+        CasterEvent::UploadComplete { records: 2 }
+    );
+    status
+        .wait_for(|s| {
+            matches!(
+                s,
+                CasterStatus::Registered {
+                    last_ping: None,
+                    ..
+                }
+            )
+        })
+        .await
+        .unwrap();
+
+    framed
+        .send(ServerMessage::Ping(Ping { nonce: 5 }))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut framed).await, "Pong nonce=5");
+    assert_eq!(
+        events.recv().await.unwrap(),
+        CasterEvent::PingAnswered { nonce: 5 }
+    );
+    status
+        .wait_for(|s| {
+            matches!(
+                s,
+                CasterStatus::Registered {
+                    last_ping: Some(_),
+                    ..
+                }
+            )
+        })
+        .await
+        .unwrap();
+
+    drop(framed);
+    assert_eq!(
+        events.recv().await.unwrap(),
+        CasterEvent::ConnectionLost(DisconnectReason::Closed)
+    );
+    assert_eq!(
+        *status.borrow_and_update(),
+        CasterStatus::Disconnected(DisconnectReason::Closed)
+    );
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+    assert_eq!(
+        *status.borrow(),
+        CasterStatus::Disconnected(DisconnectReason::Stopped)
+    );
+}
+
+#[tokio::test]
+async fn refused_connection_is_not_reported_as_lost() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap();
+    drop(listener);
+
+    let caster = Reccaster::builder()
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut status = handle.status();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, server.port()).await;
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        CasterEvent::AnnouncementSeen(_)
+    ));
+    assert_eq!(
+        events.recv().await.unwrap(),
+        CasterEvent::ConnectFailed {
+            server,
+            reason: io::ErrorKind::ConnectionRefused
+        }
+    );
+    status
+        .wait_for(|s| {
+            *s == CasterStatus::Disconnected(DisconnectReason::ConnectFailed(
+                io::ErrorKind::ConnectionRefused,
+            ))
+        })
+        .await
+        .unwrap();
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn silent_server_is_dropped_after_the_ping_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = listener.local_addr().unwrap().port();
+
+    let caster = Reccaster::builder()
+        .records(vec![Record::new("DEV:A".into(), "ai".into())])
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout_factor(3)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, server_port).await;
+    let (mut framed, _, _) = accept_upload(&listener).await;
+
+    // A ping inside the timeout keeps the connection alive
+    sleep(Duration::from_secs(25)).await;
+    framed
+        .send(ServerMessage::Ping(Ping { nonce: 1 }))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut framed).await, "Pong nonce=1");
+    let pinged = Instant::now();
+
+    assert_eq!(
+        connection_lost(&mut events).await,
+        DisconnectReason::PingTimeout
+    );
+    let silent = pinged.elapsed();
+    assert!(
+        silent >= Duration::from_secs(30) && silent < Duration::from_secs(31),
+        "dropped after {:?}",
+        silent
+    );
+    assert_eq!(
+        *handle.status().borrow(),
+        CasterStatus::Disconnected(DisconnectReason::PingTimeout)
+    );
+
+    // Back to discovery: the next announcement registers again
+    announce(listen_addr, server_port).await;
+    let (_framed, _, received) = accept_upload(&listener).await;
+    assert_eq!(received.last().unwrap(), "UploadDone");
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn server_that_never_greets_is_dropped_after_the_ping_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = listener.local_addr().unwrap().port();
+
+    let caster = Reccaster::builder()
+        .records(vec![Record::new("DEV:A".into(), "ai".into())])
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout_factor(3)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, server_port).await;
+    let (_stream, _) = listener.accept().await.unwrap();
+    let accepted = Instant::now();
+
+    assert_eq!(
+        connection_lost(&mut events).await,
+        DisconnectReason::PingTimeout
+    );
+    let waited = accepted.elapsed();
+    assert!(
+        waited >= Duration::from_secs(30) && waited < Duration::from_secs(31),
+        "dropped after {:?}",
+        waited
+    );
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+}
+
+/// Wait for the caster to report a lost connection.
+async fn connection_lost(events: &mut broadcast::Receiver<CasterEvent>) -> DisconnectReason {
+    loop {
+        if let CasterEvent::ConnectionLost(reason) = events.recv().await.unwrap() {
+            return reason;
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn zero_ping_settings_are_raised_to_a_minimum() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let caster = Reccaster::builder()
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .ping_interval(Duration::ZERO)
+        .ping_timeout_factor(0)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, listener.local_addr().unwrap().port()).await;
+    let (mut framed, _, _) = accept_upload(&listener).await;
+    sleep(Duration::from_millis(500)).await;
+    framed
+        .send(ServerMessage::Ping(Ping { nonce: 1 }))
+        .await
+        .unwrap();
+    assert_eq!(recv(&mut framed).await, "Pong nonce=1");
+    let pinged = Instant::now();
+
+    assert_eq!(
+        connection_lost(&mut events).await,
+        DisconnectReason::PingTimeout
+    );
+    let silent = pinged.elapsed();
+    assert!(
+        silent >= Duration::from_secs(1) && silent < Duration::from_secs(2),
+        "dropped after {:?}",
+        silent
+    );
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_pings_keep_the_connection_alive() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let caster = Reccaster::builder()
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout_factor(3)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, listener.local_addr().unwrap().port()).await;
+    let (mut framed, _, _) = accept_upload(&listener).await;
+    let uploaded = Instant::now();
+    for _ in 0..2 {
+        sleep(Duration::from_secs(10)).await;
+        framed
+            .send(ServerMessage::Unknown {
+                msg_id: 0x8010,
+                body: vec![0; 4],
+            })
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        connection_lost(&mut events).await,
+        DisconnectReason::PingTimeout
+    );
+    let silent = uploaded.elapsed();
+    assert!(
+        silent >= Duration::from_secs(30) && silent < Duration::from_secs(31),
+        "dropped after {:?}",
+        silent
+    );
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn send_to_a_server_that_stopped_reading_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let caster = Reccaster::builder()
+        .listen_addr(Ipv4Addr::LOCALHOST.into())
+        .listen_port(0)
+        .tcp_send_buffer_size(4096)
+        .ping_interval(Duration::from_secs(10))
+        .ping_timeout_factor(3)
+        .build()
+        .await
+        .unwrap();
+    let listen_addr = caster.listen_addr().unwrap();
+    let handle = caster.handle();
+    let mut events = handle.events();
+    let task = tokio::spawn(caster.run());
+
+    announce(listen_addr, listener.local_addr().unwrap().port()).await;
+    let (_framed, _, _) = accept_upload(&listener).await;
+
+    // Far more than the socket buffers hold, and never read
+    for i in 0..400 {
+        let mut record = Record::new(format!("DEV:BIG{}", i), "ai".into());
+        record
+            .properties
+            .insert("recordDesc".into(), "x".repeat(60_000));
+        handle.add_record(record).unwrap();
+    }
+
+    let reason = tokio::time::timeout(Duration::from_secs(120), connection_lost(&mut events))
+        .await
+        .expect("caster stuck in a send");
+    assert_eq!(reason, DisconnectReason::PingTimeout);
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+}
@@ -0,0 +1,25 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! Announcement parsing must never panic, and anything it accepts must
+//! encode back to the same fields.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::net::SocketAddr;
+use wire::Announcement;
+
+fuzz_target!(|data: &[u8]| {
+    for src in ["192.168.1.10:5049", "[::1]:5049"] {
+        let src: SocketAddr = src.parse().unwrap();
+        if let Ok(announcement) = Announcement::decode(data, src) {
+            let encoded = announcement.encode();
+            assert_eq!(Announcement::decode(&encoded, src).unwrap(), announcement);
+        }
+    }
+});
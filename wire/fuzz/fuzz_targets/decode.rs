@@ -0,0 +1,42 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! Feed arbitrary bytes to every decoder. None of them may panic, and the
+//! codec must agree with the sans-IO decoder it wraps.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use tokio_util::codec::Decoder;
+use wire::{decode_from, decode_ref_from, encode_into, MessageCodec};
+
+fuzz_target!(|data: &[u8]| {
+    let mut codec = MessageCodec::new();
+    let mut src = BytesMut::from(data);
+    let mut rest = data;
+    loop {
+        let decoded = codec.decode(&mut src);
+        let expected = decode_from(rest);
+        let _ = decode_ref_from(rest).map(|res| res.map(|(msg, _)| msg.into_owned()));
+        match (decoded, expected) {
+            (Ok(Some(msg)), Ok(Some((expected, len)))) => {
+                assert_eq!(msg, expected);
+                rest = &rest[len..];
+
+                // Whatever decodes must encode again and decode to the same message
+                let mut buf = Vec::new();
+                encode_into(&msg, &mut buf).unwrap();
+                let (again, len) = decode_from(&buf).unwrap().unwrap();
+                assert_eq!(again, msg);
+                assert_eq!(len, buf.len());
+            }
+            (Ok(None), Ok(None)) | (Err(_), Err(_)) => break,
+            (decoded, expected) => panic!("codec {:?} != decode_from {:?}", decoded, expected),
+        }
+    }
+});
@@ -0,0 +1,103 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use crate::{Error, ProtocolVersion, MSG_MAGIC_ID};
+use bytes::{BufMut, BytesMut};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// Size of an encoded UDP announcement in bytes.
+pub const ANNOUNCEMENT_LEN: usize = 16;
+
+/// UDP Announcement message structure.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Announcement {
+    /// Magic ID identifying this as a RecSync announcement.
+    pub id: u16,
+    /// Highest protocol version the server supports.
+    pub version: ProtocolVersion,
+    /// IPv4 address of the announcing server.
+    pub server_addr: Ipv4Addr,
+    /// TCP port the server is listening on.
+    pub server_port: u16,
+    /// Server-generated session key.
+    pub server_key: u32,
+}
+
+impl Announcement {
+    /// Create a new announcement for a server listening on `server_addr:server_port`.
+    pub fn new(server_addr: Ipv4Addr, server_port: u16, server_key: u32) -> Announcement {
+        Announcement {
+            id: MSG_MAGIC_ID,
+            version: ProtocolVersion::CURRENT,
+            server_addr,
+            server_port,
+            server_key,
+        }
+    }
+
+    /// Decode an announcement datagram received from `src_addr`.
+    ///
+    /// A broadcast server address is replaced by the address the datagram
+    /// was sent from. Bytes past the end of the announcement are ignored.
+    pub fn decode(data: &[u8], src_addr: SocketAddr) -> Result<Announcement, Error> {
+        if data.len() < ANNOUNCEMENT_LEN {
+            return Err(Error::Truncated {
+                expected: ANNOUNCEMENT_LEN,
+                actual: data.len(),
+            });
+        }
+
+        let id = u16::from_be_bytes([data[0], data[1]]);
+        // Checking if the ID is 'RC'
+        if id != MSG_MAGIC_ID {
+            return Err(Error::BadMagic(id));
+        }
+
+        // Newer versions are accepted, the version to use is negotiated
+        // when the client greets the server
+        let version = ProtocolVersion::new(data[2]);
+
+        // Extracting the server address (IPv4, 4 bytes)
+        let mut server_addr = Ipv4Addr::new(data[4], data[5], data[6], data[7]);
+        if server_addr.is_broadcast() {
+            match src_addr.ip() {
+                IpAddr::V4(addr) => {
+                    server_addr = addr;
+                }
+                IpAddr::V6(_) => {
+                    return Err(Error::UnsupportedAddress(src_addr));
+                }
+            }
+        }
+
+        let server_port = u16::from_be_bytes([data[8], data[9]]);
+
+        let server_key = u32::from_be_bytes([data[12], data[13], data[14], data[15]]);
+
+        Ok(Announcement {
+            id,
+            version,
+            server_addr,
+            server_port,
+            server_key,
+        })
+    }
+
+    /// Return the announcement as it is sent on the wire.
+    pub fn encode(&self) -> BytesMut {
+        let mut buf = BytesMut::with_capacity(ANNOUNCEMENT_LEN);
+        buf.put_u16(self.id);
+        buf.put_u8(self.version.into());
+        buf.put_u8(0); // Padding
+        buf.put_slice(&self.server_addr.octets());
+        buf.put_u16(self.server_port);
+        buf.put_u16(0); // Padding
+        buf.put_u32(self.server_key);
+        buf
+    }
+}
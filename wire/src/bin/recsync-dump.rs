@@ -0,0 +1,128 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! Print the recsync traffic found in a pcap or pcapng capture.
+//!
+//! Usage: `recsync-dump [--port PORT]... CAPTURE`
+//!
+//! Announcements are listed first, then the messages of each TCP connection
+//! in capture order. Frames that fail to decode are marked with `!!`.
+
+use std::{collections::BTreeMap, env, fs::File, io::BufReader, process::ExitCode};
+use wire::pcap::{read_capture, Event, EventKind};
+
+const USAGE: &str = "usage: recsync-dump [--port PORT]... CAPTURE";
+
+fn main() -> ExitCode {
+    let mut ports = Vec::new();
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" | "-p" => match args.next().and_then(|port| port.parse().ok()) {
+                Some(port) => ports.push(port),
+                None => {
+                    eprintln!("--port needs a TCP port number\n{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let events = match read_capture(BufReader::new(file), &ports) {
+        Ok(events) => events,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut announcements = Vec::new();
+    let mut conns: BTreeMap<usize, Vec<&Event>> = BTreeMap::new();
+    for event in &events {
+        match event.kind {
+            EventKind::Announcement(_) | EventKind::InvalidAnnouncement(_) => {
+                announcements.push(event)
+            }
+            EventKind::Message { conn, .. }
+            | EventKind::DecodeError { conn, .. }
+            | EventKind::StreamGap { conn } => conns.entry(conn).or_default().push(event),
+        }
+    }
+
+    let mut failed = false;
+    if !announcements.is_empty() {
+        println!("== announcements");
+        for event in announcements {
+            failed |= print_event(event);
+        }
+    }
+    for (conn, events) in conns {
+        // The first frame of a connection normally comes from the server
+        let first = events[0];
+        println!("== connection #{} {} <-> {}", conn, first.src, first.dst);
+        for event in events {
+            failed |= print_event(event);
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Print one line for `event`, returning whether it reports a decode failure.
+fn print_event(event: &Event) -> bool {
+    let ts = format!("{}.{:06}", event.ts.as_secs(), event.ts.subsec_micros());
+    let route = format!("{} -> {}", event.src, event.dst);
+    match &event.kind {
+        EventKind::Announcement(announcement) => {
+            println!("{}  {}  {}", ts, route, announcement);
+            false
+        }
+        EventKind::Message { msg, .. } => {
+            println!("{}  {}  {}", ts, route, msg);
+            false
+        }
+        EventKind::InvalidAnnouncement(err) | EventKind::DecodeError { err, .. } => {
+            println!("{}  {}  !! {}", ts, route, err);
+            true
+        }
+        EventKind::StreamGap { .. } => {
+            println!(
+                "{}  {}  !! segment missing, rest of stream skipped",
+                ts, route
+            );
+            true
+        }
+    }
+}
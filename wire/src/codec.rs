@@ -5,151 +5,191 @@
 // You must comply with both licenses to use, modify, or distribute this software.
 // See the LICENSE file for details.
 
-use bytes::{Buf, BufMut, BytesMut};
-use std::{io, mem::size_of};
+use bytes::{Buf, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
-use crate::{header::MessageHeader, ClientGreet, Message, MessageID, Ping, Pong, ServerGreet};
+use crate::{
+    check_header, decode_from_with_max, encode_into, header::MessageHeader, ClientMessage, Error,
+    Message, ServerMessage, DEFAULT_MAX_FRAME_LEN,
+};
 
-/// UDP broadcast port
-pub const SERVER_ANNOUNCEMENT_UDP_PORT: u16 = 5049;
+/// Encoders and Decoders for Messages
+#[derive(Debug, Clone)]
+pub struct MessageCodec {
+    max_frame: usize,
+}
 
-/// Message ID Magic number (ascii "RC")
-pub const MSG_MAGIC_ID: u16 = 0x5243;
+impl MessageCodec {
+    /// Create a codec that accepts frames up to `DEFAULT_MAX_FRAME_LEN` bytes.
+    pub fn new() -> MessageCodec {
+        MessageCodec::with_max_frame(DEFAULT_MAX_FRAME_LEN)
+    }
 
-/// Encoders and Decoders for Messages
-pub struct MessageCodec;
+    /// Create a codec that rejects frames larger than `max_frame` bytes,
+    /// header included, before buffering their body.
+    pub fn with_max_frame(max_frame: usize) -> MessageCodec {
+        MessageCodec { max_frame }
+    }
+
+    /// Largest frame this codec accepts.
+    pub fn max_frame(&self) -> usize {
+        self.max_frame
+    }
+}
+
+impl Default for MessageCodec {
+    fn default() -> Self {
+        MessageCodec::new()
+    }
+}
+
+/// Decoder that splits a byte stream into complete, unparsed frames.
+///
+/// Each frame includes its header and can be passed to `decode_ref_from`
+/// to read it without copying its strings.
+#[derive(Debug, Default, Clone)]
+pub struct FrameCodec {
+    inner: MessageCodec,
+}
+
+impl FrameCodec {
+    /// Create a codec that rejects frames larger than `max_frame` bytes.
+    pub fn with_max_frame(max_frame: usize) -> FrameCodec {
+        FrameCodec {
+            inner: MessageCodec::with_max_frame(max_frame),
+        }
+    }
+}
+
+/// Codec for the client (RecCaster) side of a connection.
+///
+/// Encodes `ClientMessage`s and decodes `ServerMessage`s.
+#[derive(Debug, Default, Clone)]
+pub struct ClientCodec {
+    inner: MessageCodec,
+}
+
+impl ClientCodec {
+    /// Create a codec that rejects frames larger than `max_frame` bytes.
+    pub fn with_max_frame(max_frame: usize) -> ClientCodec {
+        ClientCodec {
+            inner: MessageCodec::with_max_frame(max_frame),
+        }
+    }
+}
+
+/// Codec for the server (RecCeiver) side of a connection.
+///
+/// Encodes `ServerMessage`s and decodes `ClientMessage`s.
+#[derive(Debug, Default, Clone)]
+pub struct ServerCodec {
+    inner: MessageCodec,
+}
+
+impl ServerCodec {
+    /// Create a codec that rejects frames larger than `max_frame` bytes.
+    pub fn with_max_frame(max_frame: usize) -> ServerCodec {
+        ServerCodec {
+            inner: MessageCodec::with_max_frame(max_frame),
+        }
+    }
+}
 
 impl Encoder<Message> for MessageCodec {
-    type Error = io::Error;
+    type Error = Error;
 
     fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        match msg {
-            Message::ClientGreet(msg) => {
-                let header = MessageHeader::new(
-                    MessageID::ClientGreet.into(),
-                    (size_of::<u32>() + size_of::<ClientGreet>()) as u32,
-                );
-                dst.put(header.as_bytes());
-                dst.put_u32(0); // Padding
-                dst.put_u32(msg.serv_key);
-                Ok(())
-            }
-            Message::Pong(msg) => {
-                let header = MessageHeader::new(MessageID::Pong as u16, size_of::<Pong>() as u32);
-                dst.put(header.as_bytes());
-                dst.put_u32(msg.nonce);
-                Ok(())
-            }
-            Message::AddRecord(msg) => {
-                let len = (size_of::<u32>()
-                    + size_of::<u8>()
-                    + size_of::<u8>()
-                    + size_of::<u16>()
-                    + msg.rtype.len()
-                    + msg.rname.len()) as u32;
-                let header = MessageHeader::new(MessageID::AddRecord.into(), len);
-                dst.put_u16(header.id);
-                dst.put_u16(header.msg_id);
-                dst.put_u32(header.len);
-                dst.put_u32(msg.recid);
-                dst.put_u8(msg.atype);
-                dst.put_u8(msg.rtlen);
-                dst.put_u16(msg.rnlen);
-                dst.put_slice(msg.rtype.as_bytes());
-                dst.put_slice(msg.rname.as_bytes());
-                Ok(())
-            }
-            Message::DelRecord(_) => todo!(),
-            Message::AddInfo(msg) => {
-                let len = (size_of::<u32>()
-                    + size_of::<u8>()
-                    + size_of::<u8>()
-                    + size_of::<u16>()
-                    + msg.key.len()
-                    + msg.value.len()) as u32;
-                let header = MessageHeader::new(MessageID::AddInfo.into(), len);
-                dst.put_u16(header.id);
-                dst.put_u16(header.msg_id);
-                dst.put_u32(header.len);
-                dst.put_u32(msg.recid);
-                dst.put_u8(msg.keylen);
-                dst.put_u8(0); // Padding
-                dst.put_u16(msg.valen);
-                dst.put_slice(msg.key.as_bytes());
-                dst.put_slice(msg.value.as_bytes());
-                Ok(())
-            }
-            Message::UploadDone(_) => {
-                let header =
-                    MessageHeader::new(MessageID::UploadDone.into(), size_of::<u32>() as u32);
-                dst.put(header.as_bytes());
-                dst.put_u32(0);
-                Ok(())
-            }
-            Message::Ping(_) => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-            Message::ServerGreet(_) => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-        }
+        encode_into(&msg, dst)
     }
 }
 
 impl Decoder for MessageCodec {
     type Item = Message;
-    type Error = io::Error;
+    type Error = Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.len() < 8 {
-            // Not enough data to read header
-            return Ok(None);
+        match decode_from_with_max(src, self.max_frame)? {
+            Some((msg, len)) => {
+                // Only a complete frame is consumed from the buffer
+                src.advance(len);
+                Ok(Some(msg))
+            }
+            None => {
+                self.reserve_frame(src)?;
+                Ok(None)
+            }
         }
+    }
+}
 
-        // Read header
-        let id = src.get_u16();
-        let msg_id = src.get_u16();
-        let len = src.get_u32() as usize;
-
-        // Checking if the ID is 'RC'
-        if id != MSG_MAGIC_ID {
-            return Ok(None);
+impl MessageCodec {
+    /// Not enough data yet, make room for the rest of the frame. The header
+    /// has been checked against the limit, so this never reserves more than
+    /// `max_frame` bytes.
+    fn reserve_frame(&self, src: &mut BytesMut) -> Result<(), Error> {
+        if let Some(header) = MessageHeader::peek(src) {
+            let frame_len = check_header(&header, self.max_frame)?;
+            src.reserve(frame_len.saturating_sub(src.len()));
         }
+        Ok(())
+    }
+}
 
-        if src.len() < len {
-            // Not enough data to read the body
+impl Decoder for FrameCodec {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let header = match MessageHeader::peek(src) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let frame_len = check_header(&header, self.inner.max_frame)?;
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
             return Ok(None);
         }
+        Ok(Some(src.split_to(frame_len).freeze()))
+    }
+}
 
-        // Match based on `msg_id` and parse accordingly
-        match msg_id.into() {
-            MessageID::ServerGreet => {
-                let _placeholder = src.get_u8();
-                Ok(Some(Message::ServerGreet(ServerGreet)))
-            }
-            MessageID::Ping => {
-                let nonce = src.get_u32();
-                Ok(Some(Message::Ping(Ping { nonce })))
-            }
-            MessageID::ClientGreet => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-            MessageID::Pong => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-            MessageID::AddRecord => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-            MessageID::DelRecord => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-            MessageID::UploadDone => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-            MessageID::AddInfo => {
-                unimplemented!("Recceiver related messages are not implemented yet.")
-            }
-        }
+impl Encoder<ClientMessage> for ClientCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: ClientMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.inner.encode(msg.into(), dst)
+    }
+}
+
+impl Decoder for ClientCodec {
+    type Item = ServerMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.inner
+            .decode(src)?
+            .map(ServerMessage::try_from)
+            .transpose()
+    }
+}
+
+impl Encoder<ServerMessage> for ServerCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: ServerMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.inner.encode(msg.into(), dst)
+    }
+}
+
+impl Decoder for ServerCodec {
+    type Item = ClientMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.inner
+            .decode(src)?
+            .map(ClientMessage::try_from)
+            .transpose()
     }
 }
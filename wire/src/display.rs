@@ -0,0 +1,148 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! One-line, protocol-level `Display` output for messages.
+
+use std::fmt;
+
+use crate::{
+    AddInfo, AddRecord, AddRecordType, Announcement, ClientGreet, ClientMessage, DelRecord,
+    Message, MessageHeader, MessageID, Ping, Pong, ServerGreet, ServerMessage, UploadDone,
+};
+
+impl fmt::Display for MessageID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl fmt::Display for MessageHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match MessageID::try_from(self.msg_id) {
+            Ok(msg_id) => write!(f, "{} len={}", msg_id, self.len),
+            Err(_) => write!(f, "msg_id={:#06x} len={}", self.msg_id, self.len),
+        }
+    }
+}
+
+impl fmt::Display for Announcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Announcement version={} server={}:{} key={:#010x}",
+            self.version, self.server_addr, self.server_port, self.server_key
+        )
+    }
+}
+
+impl fmt::Display for ServerGreet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ServerGreet version={}", self.version)
+    }
+}
+
+impl fmt::Display for Ping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ping nonce={}", self.nonce)
+    }
+}
+
+impl fmt::Display for ClientGreet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ClientGreet version={} key={:#010x}",
+            self.version, self.serv_key
+        )
+    }
+}
+
+impl fmt::Display for Pong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pong nonce={}", self.nonce)
+    }
+}
+
+impl fmt::Display for AddRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let atype = match self.atype {
+            AddRecordType::Record => "record",
+            AddRecordType::Alias => "alias",
+        };
+        write!(
+            f,
+            "AddRecord recid={} {} type={} name={}",
+            self.recid, atype, self.rtype, self.rname
+        )
+    }
+}
+
+impl fmt::Display for DelRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DelRecord recid={}", self.recid)
+    }
+}
+
+impl fmt::Display for UploadDone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UploadDone")
+    }
+}
+
+impl fmt::Display for AddInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AddInfo recid={} key={:?} value={:?}",
+            self.recid, self.key, self.value
+        )
+    }
+}
+
+fn fmt_unknown(f: &mut fmt::Formatter<'_>, msg_id: u16, body: &[u8]) -> fmt::Result {
+    write!(f, "Unknown msg_id={:#06x} len={}", msg_id, body.len())
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::ServerGreet(msg) => msg.fmt(f),
+            Message::Ping(msg) => msg.fmt(f),
+            Message::ClientGreet(msg) => msg.fmt(f),
+            Message::Pong(msg) => msg.fmt(f),
+            Message::AddRecord(msg) => msg.fmt(f),
+            Message::DelRecord(msg) => msg.fmt(f),
+            Message::UploadDone(msg) => msg.fmt(f),
+            Message::AddInfo(msg) => msg.fmt(f),
+            Message::Unknown { msg_id, body } => fmt_unknown(f, *msg_id, body),
+        }
+    }
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessage::ClientGreet(msg) => msg.fmt(f),
+            ClientMessage::Pong(msg) => msg.fmt(f),
+            ClientMessage::AddRecord(msg) => msg.fmt(f),
+            ClientMessage::DelRecord(msg) => msg.fmt(f),
+            ClientMessage::UploadDone(msg) => msg.fmt(f),
+            ClientMessage::AddInfo(msg) => msg.fmt(f),
+            ClientMessage::Unknown { msg_id, body } => fmt_unknown(f, *msg_id, body),
+        }
+    }
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::ServerGreet(msg) => msg.fmt(f),
+            ServerMessage::Ping(msg) => msg.fmt(f),
+            ServerMessage::Unknown { msg_id, body } => fmt_unknown(f, *msg_id, body),
+        }
+    }
+}
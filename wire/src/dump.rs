@@ -0,0 +1,201 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! Annotated hexdumps of wire protocol byte streams.
+
+use std::fmt::Write;
+
+use crate::{decode_from, MessageHeader, MessageID, HEADER_LEN, MSG_MAGIC_ID};
+
+/// Number of bytes shown on one hexdump line.
+const BYTES_PER_LINE: usize = 16;
+
+/// Render a stream of wire protocol frames as a hexdump with every header
+/// field, body field and padding byte labelled.
+///
+/// Each frame is followed by its decoded form. Dumping stops at the first
+/// frame that cannot be followed (bad magic or missing bytes), and the rest
+/// of the input is shown unlabelled.
+pub fn annotate(data: &[u8]) -> String {
+    let mut dump = Dump {
+        out: String::new(),
+        data,
+        pos: 0,
+    };
+    while dump.pos < data.len() {
+        if !dump.frame() {
+            break;
+        }
+    }
+    dump.out
+}
+
+struct Dump<'a> {
+    out: String,
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// Layout of one field of a message body.
+enum Field {
+    Int(usize, &'static str),
+    Padding(usize),
+    Str(usize, &'static str),
+}
+
+impl Dump<'_> {
+    /// Annotate the frame starting at `self.pos`. Returns false if the
+    /// stream cannot be followed past it.
+    fn frame(&mut self) -> bool {
+        let start = self.pos;
+        let header = match MessageHeader::peek(&self.data[start..]) {
+            Some(header) => header,
+            None => {
+                self.rest("incomplete header");
+                return false;
+            }
+        };
+
+        if header.id == MSG_MAGIC_ID {
+            self.field(2, "magic \"RC\"".to_string());
+        } else {
+            self.field(2, format!("bad magic {:#06x}", header.id));
+            self.rest("not followed");
+            return false;
+        }
+        let msg_id = MessageID::try_from(header.msg_id).ok();
+        match msg_id {
+            Some(msg_id) => self.field(2, format!("msg_id {}", msg_id)),
+            None => self.field(2, format!("msg_id {:#06x} (unknown)", header.msg_id)),
+        }
+        self.field(4, format!("len {}", header.len));
+
+        let body_end = start + HEADER_LEN + header.len as usize;
+        if body_end > self.data.len() {
+            self.rest("incomplete body");
+            return false;
+        }
+
+        let layout = match msg_id {
+            Some(msg_id) => self.layout(msg_id, body_end),
+            None => Vec::new(),
+        };
+        for field in layout {
+            let len = match field {
+                Field::Int(len, _) | Field::Padding(len) | Field::Str(len, _) => len,
+            };
+            if self.pos + len > body_end {
+                self.field(body_end - self.pos, "truncated field".to_string());
+                break;
+            }
+            let bytes = &self.data[self.pos..self.pos + len];
+            let label = match field {
+                Field::Int(_, name) => {
+                    let value = bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+                    format!("{} {}", name, value)
+                }
+                Field::Padding(_) => "padding".to_string(),
+                Field::Str(_, name) => format!("{} {:?}", name, String::from_utf8_lossy(bytes)),
+            };
+            self.field(len, label);
+        }
+        if self.pos < body_end {
+            let label = if msg_id.is_some() { "trailing" } else { "body" };
+            self.field(body_end - self.pos, label.to_string());
+        }
+
+        match decode_from(&self.data[start..body_end]) {
+            Ok(Some((msg, _))) => writeln!(self.out, "      => {}", msg),
+            Ok(None) => writeln!(self.out, "      => incomplete"),
+            Err(err) => writeln!(self.out, "      => error: {}", err),
+        }
+        .expect("writing to a String cannot fail");
+        true
+    }
+
+    /// Field layout of a known message body. String lengths are read from
+    /// the body itself.
+    fn layout(&self, msg_id: MessageID, body_end: usize) -> Vec<Field> {
+        let body = &self.data[self.pos..body_end];
+        match msg_id {
+            MessageID::ServerGreet => vec![Field::Int(1, "version")],
+            MessageID::Ping | MessageID::Pong => vec![Field::Int(4, "nonce")],
+            MessageID::ClientGreet => vec![
+                Field::Int(1, "version"),
+                Field::Int(1, "type"),
+                Field::Padding(2),
+                Field::Int(4, "serv_key"),
+            ],
+            MessageID::AddRecord => {
+                let mut fields = vec![
+                    Field::Int(4, "recid"),
+                    Field::Int(1, "atype"),
+                    Field::Int(1, "rtlen"),
+                    Field::Int(2, "rnlen"),
+                ];
+                if body.len() >= 8 {
+                    fields.push(Field::Str(body[5] as usize, "rtype"));
+                    fields.push(Field::Str(
+                        u16::from_be_bytes([body[6], body[7]]) as usize,
+                        "rname",
+                    ));
+                }
+                fields
+            }
+            MessageID::DelRecord => vec![Field::Int(4, "recid")],
+            MessageID::UploadDone => vec![Field::Padding(body.len().min(4))],
+            MessageID::AddInfo => {
+                let mut fields = vec![
+                    Field::Int(4, "recid"),
+                    Field::Int(1, "keylen"),
+                    Field::Padding(1),
+                    Field::Int(2, "valen"),
+                ];
+                if body.len() >= 8 {
+                    fields.push(Field::Str(body[4] as usize, "key"));
+                    fields.push(Field::Str(
+                        u16::from_be_bytes([body[6], body[7]]) as usize,
+                        "value",
+                    ));
+                }
+                fields
+            }
+        }
+    }
+
+    /// Dump `len` bytes at the current position with `label` on the first line.
+    fn field(&mut self, len: usize, label: String) {
+        let bytes = &self.data[self.pos..self.pos + len];
+        if bytes.is_empty() {
+            self.line(self.pos, &[], &label);
+        }
+        for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let label = if i == 0 { label.as_str() } else { "" };
+            self.line(self.pos + i * BYTES_PER_LINE, chunk, label);
+        }
+        self.pos += len;
+    }
+
+    fn line(&mut self, offset: usize, bytes: &[u8], label: &str) {
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let line = format!(
+            "{:04x}  {:width$}  {}",
+            offset,
+            hex.join(" "),
+            label,
+            width = BYTES_PER_LINE * 3 - 1
+        );
+        self.out.push_str(line.trim_end());
+        self.out.push('\n');
+    }
+
+    /// Dump everything left in the input under `label`.
+    fn rest(&mut self, label: &str) {
+        let len = self.data.len() - self.pos;
+        self.field(len, label.to_string());
+    }
+}
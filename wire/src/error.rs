@@ -0,0 +1,121 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use std::{fmt, io, net::SocketAddr, str::Utf8Error, string::FromUtf8Error};
+
+/// Errors produced while encoding or decoding wire protocol messages.
+#[derive(Debug)]
+pub enum Error {
+    /// The message did not start with `MSG_MAGIC_ID`.
+    BadMagic(u16),
+    /// The header carried a message ID that is not part of the protocol.
+    UnknownMessageId(u16),
+    /// An AddRecord carried a record type that is not part of the protocol.
+    UnknownRecordType(u8),
+    /// A message was received that is not valid in this direction.
+    UnexpectedMessage(u16),
+    /// The body is shorter than the fixed part of the message.
+    Truncated {
+        /// Minimum number of bytes required.
+        expected: usize,
+        /// Number of bytes available.
+        actual: usize,
+    },
+    /// The length fields inside the message describe more bytes than the body holds.
+    LengthMismatch {
+        /// Length implied by the message's own length fields.
+        expected: usize,
+        /// Length declared in the header.
+        actual: usize,
+    },
+    /// An announcement could not be resolved to an IPv4 server address.
+    UnsupportedAddress(SocketAddr),
+    /// A frame is larger than the configured or protocol limit.
+    FrameTooLarge {
+        /// Length of the frame in bytes, header included.
+        len: usize,
+        /// Largest frame allowed.
+        max: usize,
+    },
+    /// A string field is too long for its length field.
+    FieldTooLong {
+        /// Name of the field.
+        field: &'static str,
+        /// Length of the string in bytes.
+        len: usize,
+        /// Maximum length the field can describe.
+        max: usize,
+    },
+    /// A string field was not valid UTF-8.
+    InvalidUtf8(Utf8Error),
+    /// An I/O error from the underlying transport.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadMagic(id) => write!(f, "bad magic {:#06x}", id),
+            Error::UnknownMessageId(id) => write!(f, "unknown message id {:#06x}", id),
+            Error::UnknownRecordType(atype) => write!(f, "unknown record type {}", atype),
+            Error::UnexpectedMessage(id) => write!(f, "unexpected message id {:#06x}", id),
+            Error::Truncated { expected, actual } => write!(
+                f,
+                "truncated body: expected at least {} bytes, got {}",
+                expected, actual
+            ),
+            Error::LengthMismatch { expected, actual } => write!(
+                f,
+                "length mismatch: length fields need {} bytes, header declares {}",
+                expected, actual
+            ),
+            Error::UnsupportedAddress(addr) => {
+                write!(f, "unsupported announcement source address {}", addr)
+            }
+            Error::FrameTooLarge { len, max } => write!(
+                f,
+                "frame of {} bytes exceeds the limit of {} bytes",
+                len, max
+            ),
+            Error::FieldTooLong { field, len, max } => write!(
+                f,
+                "field {} is {} bytes long, at most {} are allowed",
+                field, len, max
+            ),
+            Error::InvalidUtf8(err) => write!(f, "invalid UTF-8 in string field: {}", err),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUtf8(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(err: Utf8Error) -> Self {
+        Error::InvalidUtf8(err)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(err: FromUtf8Error) -> Self {
+        Error::InvalidUtf8(err.utf8_error())
+    }
+}
@@ -0,0 +1,334 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use bytes::{Buf, BufMut};
+use std::mem::size_of;
+
+use crate::{
+    header::{MessageHeader, HEADER_LEN},
+    AddInfoRef, AddRecordRef, AddRecordType, ClientGreet, DelRecord, Error, Message, MessageID,
+    MessageRef, Ping, Pong, ServerGreet, UploadDone,
+};
+
+/// UDP broadcast port
+pub const SERVER_ANNOUNCEMENT_UDP_PORT: u16 = 5049;
+
+/// Message ID Magic number (ascii "RC")
+pub const MSG_MAGIC_ID: u16 = 0x5243;
+
+/// Size of a ClientGreet body (version, client type, padding, serv_key).
+const CLIENT_GREET_LEN: usize = 8;
+
+/// Size of the fixed part of an AddRecord body (recid, atype, rtlen, rnlen).
+const ADD_RECORD_FIXED_LEN: usize = 8;
+
+/// Size of the fixed part of an AddInfo body (recid, keylen, padding, valen).
+const ADD_INFO_FIXED_LEN: usize = 8;
+
+/// Largest body an AddRecord or AddInfo can have: the fixed part plus the
+/// longest strings their u8 and u16 length fields can describe.
+const MAX_STRING_BODY_LEN: usize = ADD_RECORD_FIXED_LEN + u8::MAX as usize + u16::MAX as usize;
+
+/// Default limit on the size of a frame, header included. This is the largest
+/// frame any known message can need.
+pub const DEFAULT_MAX_FRAME_LEN: usize = HEADER_LEN + MAX_STRING_BODY_LEN;
+
+/// Encode `msg`, header included, into `dst`.
+///
+/// Nothing is written if the message fails validation.
+pub fn encode_into(msg: &Message, dst: &mut impl BufMut) -> Result<(), Error> {
+    match msg {
+        Message::ClientGreet(msg) => {
+            let header = MessageHeader::new(MessageID::ClientGreet.into(), CLIENT_GREET_LEN as u32);
+            dst.put(header.as_bytes());
+            dst.put_u8(msg.version.into());
+            dst.put_u8(0); // Client type
+            dst.put_u16(0); // Padding
+            dst.put_u32(msg.serv_key);
+            Ok(())
+        }
+        Message::Pong(msg) => {
+            let header = MessageHeader::new(MessageID::Pong as u16, size_of::<Pong>() as u32);
+            dst.put(header.as_bytes());
+            dst.put_u32(msg.nonce);
+            Ok(())
+        }
+        Message::AddRecord(msg) => {
+            msg.check_lengths()?;
+            let len = (size_of::<u32>()
+                + size_of::<u8>()
+                + size_of::<u8>()
+                + size_of::<u16>()
+                + msg.rtype.len()
+                + msg.rname.len()) as u32;
+            let header = MessageHeader::new(MessageID::AddRecord.into(), len);
+            dst.put_u16(header.id);
+            dst.put_u16(header.msg_id);
+            dst.put_u32(header.len);
+            dst.put_u32(msg.recid);
+            dst.put_u8(msg.atype.into());
+            dst.put_u8(msg.rtype.len() as u8);
+            dst.put_u16(msg.rname.len() as u16);
+            dst.put_slice(msg.rtype.as_bytes());
+            dst.put_slice(msg.rname.as_bytes());
+            Ok(())
+        }
+        Message::DelRecord(msg) => {
+            let header =
+                MessageHeader::new(MessageID::DelRecord.into(), size_of::<DelRecord>() as u32);
+            dst.put(header.as_bytes());
+            dst.put_u32(msg.recid);
+            Ok(())
+        }
+        Message::AddInfo(msg) => {
+            msg.check_lengths()?;
+            let len = (size_of::<u32>()
+                + size_of::<u8>()
+                + size_of::<u8>()
+                + size_of::<u16>()
+                + msg.key.len()
+                + msg.value.len()) as u32;
+            let header = MessageHeader::new(MessageID::AddInfo.into(), len);
+            dst.put_u16(header.id);
+            dst.put_u16(header.msg_id);
+            dst.put_u32(header.len);
+            dst.put_u32(msg.recid);
+            dst.put_u8(msg.key.len() as u8);
+            dst.put_u8(0); // Padding
+            dst.put_u16(msg.value.len() as u16);
+            dst.put_slice(msg.key.as_bytes());
+            dst.put_slice(msg.value.as_bytes());
+            Ok(())
+        }
+        Message::UploadDone(_) => {
+            let header = MessageHeader::new(MessageID::UploadDone.into(), size_of::<u32>() as u32);
+            dst.put(header.as_bytes());
+            dst.put_u32(0);
+            Ok(())
+        }
+        Message::Ping(msg) => {
+            let header = MessageHeader::new(MessageID::Ping.into(), size_of::<Ping>() as u32);
+            dst.put(header.as_bytes());
+            dst.put_u32(msg.nonce);
+            Ok(())
+        }
+        Message::ServerGreet(msg) => {
+            let header = MessageHeader::new(MessageID::ServerGreet.into(), size_of::<u8>() as u32);
+            dst.put(header.as_bytes());
+            dst.put_u8(msg.version.into());
+            Ok(())
+        }
+        Message::Unknown { msg_id, body } => {
+            let header = MessageHeader::new(*msg_id, body.len() as u32);
+            dst.put(header.as_bytes());
+            dst.put_slice(body);
+            Ok(())
+        }
+    }
+}
+
+/// Decode one message from the start of `src`.
+///
+/// Returns the message and the number of bytes it occupied, or `None` if
+/// `src` does not yet hold a complete frame. Nothing is consumed; the caller
+/// is responsible for discarding the returned number of bytes.
+///
+/// Frames larger than `DEFAULT_MAX_FRAME_LEN` are rejected.
+pub fn decode_from(src: &[u8]) -> Result<Option<(Message, usize)>, Error> {
+    decode_from_with_max(src, DEFAULT_MAX_FRAME_LEN)
+}
+
+/// Like `decode_from`, rejecting frames larger than `max_frame` bytes.
+pub fn decode_from_with_max(
+    src: &[u8],
+    max_frame: usize,
+) -> Result<Option<(Message, usize)>, Error> {
+    match decode_ref_from_with_max(src, max_frame)? {
+        Some((msg, len)) => Ok(Some((msg.into_owned()?, len))),
+        None => Ok(None),
+    }
+}
+
+/// Decode one message from the start of `src` without copying its strings.
+///
+/// Behaves like `decode_from`, except that string fields borrow from `src`
+/// and are only checked for valid UTF-8 when they are accessed.
+pub fn decode_ref_from(src: &[u8]) -> Result<Option<(MessageRef<'_>, usize)>, Error> {
+    decode_ref_from_with_max(src, DEFAULT_MAX_FRAME_LEN)
+}
+
+/// Like `decode_ref_from`, rejecting frames larger than `max_frame` bytes.
+pub fn decode_ref_from_with_max(
+    src: &[u8],
+    max_frame: usize,
+) -> Result<Option<(MessageRef<'_>, usize)>, Error> {
+    let header = match MessageHeader::peek(src) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let frame_len = check_header(&header, max_frame)?;
+    if src.len() < frame_len {
+        // Not enough data to read the body
+        return Ok(None);
+    }
+
+    // Each message reads what it knows from `body`; any trailing bytes added
+    // by a newer peer are skipped along with the rest of the frame.
+    let body = &src[HEADER_LEN..frame_len];
+    let msg = match MessageID::try_from(header.msg_id) {
+        Ok(msg_id) => decode_body(msg_id, body)?,
+        Err(_) => MessageRef::Unknown {
+            msg_id: header.msg_id,
+            body,
+        },
+    };
+    Ok(Some((msg, frame_len)))
+}
+
+/// Decode the body of a known message type. `body` holds exactly the number
+/// of bytes announced in the header.
+fn decode_body(msg_id: MessageID, mut body: &[u8]) -> Result<MessageRef<'_>, Error> {
+    let len = body.len();
+
+    // Match based on `msg_id` and parse accordingly
+    match msg_id {
+        MessageID::ServerGreet => {
+            let version = body.get_u8().into();
+            Ok(MessageRef::ServerGreet(ServerGreet { version }))
+        }
+        MessageID::Ping => {
+            let nonce = body.get_u32();
+            Ok(MessageRef::Ping(Ping { nonce }))
+        }
+        MessageID::ClientGreet => {
+            let version = body.get_u8().into();
+            let _client_type = body.get_u8();
+            let _padding = body.get_u16();
+            let serv_key = body.get_u32();
+            Ok(MessageRef::ClientGreet(ClientGreet { version, serv_key }))
+        }
+        MessageID::Pong => {
+            let nonce = body.get_u32();
+            Ok(MessageRef::Pong(Pong { nonce }))
+        }
+        MessageID::AddRecord => {
+            let recid = body.get_u32();
+            let atype = AddRecordType::try_from(body.get_u8())?;
+            let rtlen = body.get_u8();
+            let rnlen = body.get_u16();
+            check_body_len(len, ADD_RECORD_FIXED_LEN + rtlen as usize + rnlen as usize)?;
+            let rtype = get_bytes(&mut body, rtlen as usize);
+            let rname = get_bytes(&mut body, rnlen as usize);
+            Ok(MessageRef::AddRecord(AddRecordRef {
+                recid,
+                atype,
+                rtype,
+                rname,
+            }))
+        }
+        MessageID::DelRecord => {
+            let recid = body.get_u32();
+            Ok(MessageRef::DelRecord(DelRecord { recid }))
+        }
+        MessageID::UploadDone => Ok(MessageRef::UploadDone(UploadDone)),
+        MessageID::AddInfo => {
+            let recid = body.get_u32();
+            let keylen = body.get_u8();
+            let _padding = body.get_u8();
+            let valen = body.get_u16();
+            check_body_len(len, ADD_INFO_FIXED_LEN + keylen as usize + valen as usize)?;
+            let key = get_bytes(&mut body, keylen as usize);
+            let value = get_bytes(&mut body, valen as usize);
+            Ok(MessageRef::AddInfo(AddInfoRef { recid, key, value }))
+        }
+    }
+}
+
+/// Validate `header` before any of its body is read, returning the length
+/// of the whole frame.
+///
+/// This rejects bad magic, bodies too short for their message type and
+/// frames larger than `max_frame` or than their message type can need, so
+/// that callers never buffer more than `max_frame` bytes for one message.
+pub fn check_header(header: &MessageHeader, max_frame: usize) -> Result<usize, Error> {
+    // Checking if the ID is 'RC'
+    if header.id != MSG_MAGIC_ID {
+        return Err(Error::BadMagic(header.id));
+    }
+
+    let len = header.len as usize;
+    let frame_len = HEADER_LEN.saturating_add(len);
+    if let Ok(msg_id) = MessageID::try_from(header.msg_id) {
+        let min_len = min_body_len(msg_id);
+        if len < min_len {
+            return Err(Error::Truncated {
+                expected: min_len,
+                actual: len,
+            });
+        }
+        if let Some(max_len) = max_body_len(msg_id) {
+            if len > max_len {
+                return Err(Error::FrameTooLarge {
+                    len: frame_len,
+                    max: HEADER_LEN + max_len,
+                });
+            }
+        }
+    }
+
+    if frame_len > max_frame {
+        return Err(Error::FrameTooLarge {
+            len: frame_len,
+            max: max_frame,
+        });
+    }
+    Ok(frame_len)
+}
+
+/// Size of the fixed part of a message body, before any strings.
+fn min_body_len(msg_id: MessageID) -> usize {
+    match msg_id {
+        MessageID::ServerGreet => size_of::<u8>(),
+        MessageID::ClientGreet => CLIENT_GREET_LEN,
+        MessageID::Ping => size_of::<Ping>(),
+        MessageID::Pong => size_of::<Pong>(),
+        MessageID::AddRecord => ADD_RECORD_FIXED_LEN,
+        MessageID::DelRecord => size_of::<DelRecord>(),
+        MessageID::UploadDone => 0,
+        MessageID::AddInfo => ADD_INFO_FIXED_LEN,
+    }
+}
+
+/// Largest body a message type can need, for messages whose size depends on
+/// their string length fields. Other messages are only bounded by the frame
+/// limit, so that newer peers can append fields to them.
+fn max_body_len(msg_id: MessageID) -> Option<usize> {
+    match msg_id {
+        MessageID::AddRecord | MessageID::AddInfo => Some(MAX_STRING_BODY_LEN),
+        _ => None,
+    }
+}
+
+/// Check that the strings described by the length fields of the message fit
+/// in the body length announced in the header.
+fn check_body_len(len: usize, expected: usize) -> Result<(), Error> {
+    if len < expected {
+        return Err(Error::LengthMismatch {
+            expected,
+            actual: len,
+        });
+    }
+    Ok(())
+}
+
+/// Split `len` bytes off the front of `src`.
+fn get_bytes<'a>(src: &mut &'a [u8], len: usize) -> &'a [u8] {
+    let (bytes, rest) = src.split_at(len);
+    *src = rest;
+    bytes
+}
@@ -7,10 +7,13 @@
 
 use crate::MSG_MAGIC_ID;
 use bytes::{BufMut, BytesMut};
-use std::mem::size_of;
+
+/// Size of the encoded message header in bytes.
+pub const HEADER_LEN: usize = 8;
 
 /// Fixed 8-byte header that precedes every wire protocol message.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MessageHeader {
     /// Magic identifier (`MSG_MAGIC_ID`, ASCII "RC").
     pub id: u16,
@@ -30,9 +33,26 @@ impl MessageHeader {
         }
     }
 
+    /// Read a header from the start of `buf` without consuming it.
+    ///
+    /// Returns `None` if fewer than `HEADER_LEN` bytes are available.
+    pub fn peek(buf: &[u8]) -> Option<MessageHeader> {
+        let bytes = buf.get(..HEADER_LEN)?.try_into().ok()?;
+        Some(MessageHeader::from_bytes(bytes))
+    }
+
+    /// Read a header from its encoded form.
+    pub fn from_bytes(buf: &[u8; HEADER_LEN]) -> MessageHeader {
+        MessageHeader {
+            id: u16::from_be_bytes([buf[0], buf[1]]),
+            msg_id: u16::from_be_bytes([buf[2], buf[3]]),
+            len: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        }
+    }
+
     /// Return Header as BytesMut
     pub fn as_bytes(&self) -> BytesMut {
-        let mut buf = BytesMut::with_capacity(size_of::<MessageHeader>());
+        let mut buf = BytesMut::with_capacity(HEADER_LEN);
         buf.put_u16(self.id);
         buf.put_u16(self.msg_id);
         buf.put_u32(self.len);
@@ -7,10 +7,27 @@
 
 //! Wire protocol types, codec, and constants for the RecSync protocol.
 
+mod announcement;
+#[cfg(feature = "tokio")]
 mod codec;
+mod display;
+pub mod dump;
+mod error;
+mod frame;
 mod header;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod sync;
 mod types;
+mod version;
+mod view;
 
+pub use announcement::*;
+#[cfg(feature = "tokio")]
 pub use codec::*;
+pub use error::Error;
+pub use frame::*;
 pub use header::*;
 pub use types::*;
+pub use version::ProtocolVersion;
+pub use view::*;
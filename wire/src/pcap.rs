@@ -0,0 +1,526 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! Offline decoding of recsync traffic from pcap and pcapng captures.
+//!
+//! `read_capture` walks a capture, decodes the UDP announcements sent to
+//! `SERVER_ANNOUNCEMENT_UDP_PORT`, reassembles the TCP streams that carry
+//! recsync traffic and returns everything as a list of `Event`s in capture
+//! order.
+//!
+//! A TCP stream is treated as recsync traffic if one of its ports was seen
+//! in an announcement, was passed in `ports`, or if its first payload starts
+//! with `MSG_MAGIC_ID`.
+
+use etherparse::{err::packet::SliceError, NetSlice, SlicedPacket, TransportSlice};
+use pcap_parser::{pcapng::Block, traits::PcapNGPacketBlock, Linktype, PcapBlockOwned, PcapError};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::Read,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use crate::{
+    decode_from,
+    header::{MessageHeader, HEADER_LEN},
+    Announcement, Error, Message, MSG_MAGIC_ID, SERVER_ANNOUNCEMENT_UDP_PORT,
+};
+
+/// Initial size of the buffer used to read the capture.
+const READ_BUFFER_LEN: usize = 65536;
+
+/// Largest size the read buffer grows to for a block that does not fit.
+/// Well above the 256 KiB snapshot length used by current capture tools.
+const MAX_READ_BUFFER_LEN: usize = 16 * 1024 * 1024;
+
+/// Most bytes held for one direction of a TCP connection while waiting for
+/// a missing segment. A segment lost from the capture would otherwise make
+/// every later one pile up.
+const MAX_PENDING_LEN: usize = 4 * 1024 * 1024;
+
+/// Something seen in a capture.
+#[derive(Debug)]
+pub struct Event {
+    /// Capture timestamp of the packet that completed the event.
+    pub ts: Duration,
+    /// Sender of the packet.
+    pub src: SocketAddr,
+    /// Receiver of the packet.
+    pub dst: SocketAddr,
+    /// What was seen.
+    pub kind: EventKind,
+}
+
+/// The kinds of `Event` found in a capture.
+#[derive(Debug)]
+pub enum EventKind {
+    /// A valid UDP announcement.
+    Announcement(Announcement),
+    /// A UDP datagram on the announcement port that failed to decode.
+    InvalidAnnouncement(Error),
+    /// A message decoded from TCP connection `conn`.
+    Message {
+        /// Connection number, counted from 1 in order of appearance.
+        conn: usize,
+        /// The decoded message.
+        msg: Message,
+    },
+    /// A frame on TCP connection `conn` that failed to decode.
+    ///
+    /// If the header was readable the frame is skipped and decoding carries
+    /// on with the next one, otherwise the rest of that direction of the
+    /// connection is ignored.
+    DecodeError {
+        /// Connection number, counted from 1 in order of appearance.
+        conn: usize,
+        /// Why the frame could not be decoded.
+        err: Error,
+    },
+    /// A segment of TCP connection `conn` is missing from the capture and
+    /// more than `MAX_PENDING_LEN` bytes arrived after it. The rest of that
+    /// direction of the connection is ignored.
+    StreamGap {
+        /// Connection number, counted from 1 in order of appearance.
+        conn: usize,
+    },
+}
+
+/// Errors produced while reading a capture file.
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The file is not a pcap or pcapng capture, or a block in it is
+    /// malformed.
+    Parse(PcapError<&'static [u8]>),
+    /// The capture ends in the middle of a block.
+    Truncated,
+    /// A block is larger than the biggest read buffer, in bytes.
+    BlockTooLarge(usize),
+    /// A pcapng interface declares a timestamp resolution that cannot be
+    /// represented.
+    InvalidTimestampResolution,
+    /// A pcapng packet refers to an interface that was not described.
+    UnknownInterface(u32),
+    /// A packet uses a link layer that cannot be decoded.
+    UnsupportedLinkType {
+        /// Packet number, counted from 1.
+        packet: usize,
+        /// Link type of the packet.
+        linktype: Linktype,
+    },
+    /// A packet could not be parsed down to its transport layer.
+    Packet {
+        /// Packet number, counted from 1.
+        packet: usize,
+        /// Why the packet could not be parsed.
+        source: SliceError,
+    },
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Parse(err) => write!(f, "invalid capture: {}", err),
+            CaptureError::Truncated => write!(f, "capture is truncated"),
+            CaptureError::BlockTooLarge(limit) => {
+                write!(f, "capture block larger than {} bytes", limit)
+            }
+            CaptureError::InvalidTimestampResolution => {
+                write!(f, "invalid interface timestamp resolution")
+            }
+            CaptureError::UnknownInterface(id) => {
+                write!(f, "packet for unknown interface {}", id)
+            }
+            CaptureError::UnsupportedLinkType { packet, linktype } => {
+                write!(f, "packet {}: unsupported link type {}", packet, linktype.0)
+            }
+            CaptureError::Packet { packet, source } => write!(f, "packet {}: {}", packet, source),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::Parse(err) => Some(err),
+            CaptureError::Packet { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl<I: AsRef<[u8]>> From<PcapError<I>> for CaptureError {
+    fn from(err: PcapError<I>) -> Self {
+        match err {
+            PcapError::UnexpectedEof | PcapError::Incomplete(_) => CaptureError::Truncated,
+            err => CaptureError::Parse(err.to_owned_vec()),
+        }
+    }
+}
+
+/// Read a pcap or pcapng capture from `reader` and decode the recsync
+/// traffic it contains.
+///
+/// `ports` lists extra TCP ports to treat as recsync servers, for captures
+/// that start after the announcement was sent.
+pub fn read_capture<R: Read + Send + 'static>(
+    reader: R,
+    ports: &[u16],
+) -> Result<Vec<Event>, CaptureError> {
+    let mut buffer_len = READ_BUFFER_LEN;
+    let mut capture = pcap_parser::create_reader(buffer_len, reader)?;
+    let mut timeline = Timeline::new(ports);
+    // Link type and timestamp precision of the legacy pcap format
+    let mut legacy = (Linktype::ETHERNET, 1_000_000);
+    // Link type, timestamp resolution and offset of each pcapng interface
+    let mut interfaces: Vec<(Linktype, u64, u64)> = Vec::new();
+
+    loop {
+        match capture.next() {
+            Ok((offset, block)) => {
+                match block {
+                    PcapBlockOwned::LegacyHeader(header) => {
+                        let resolution = if header.is_nanosecond_precision() {
+                            1_000_000_000
+                        } else {
+                            1_000_000
+                        };
+                        legacy = (header.network, resolution);
+                    }
+                    PcapBlockOwned::Legacy(packet) => {
+                        let ts = timestamp(packet.ts_sec, packet.ts_usec, legacy.1);
+                        timeline.packet(ts, legacy.0, packet.data)?;
+                    }
+                    PcapBlockOwned::NG(Block::SectionHeader(_)) => interfaces.clear(),
+                    PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                        let resolution = idb
+                            .ts_resolution()
+                            .ok_or(CaptureError::InvalidTimestampResolution)?;
+                        interfaces.push((idb.linktype, resolution, idb.ts_offset() as u64));
+                    }
+                    PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                        let (linktype, resolution, offset) = *interfaces
+                            .get(epb.if_id as usize)
+                            .ok_or(CaptureError::UnknownInterface(epb.if_id))?;
+                        let (sec, frac) = epb.decode_ts(offset, resolution);
+                        timeline.packet(
+                            timestamp(sec, frac, resolution),
+                            linktype,
+                            epb.packet_data(),
+                        )?;
+                    }
+                    PcapBlockOwned::NG(Block::SimplePacket(spb)) => {
+                        // Simple packets have no timestamp, reuse the last one seen
+                        let (linktype, _, _) = *interfaces
+                            .first()
+                            .ok_or(CaptureError::UnknownInterface(0))?;
+                        timeline.packet(timeline.last_ts, linktype, spb.packet_data())?;
+                    }
+                    PcapBlockOwned::NG(_) => {}
+                }
+                capture.consume(offset);
+            }
+            Err(PcapError::Eof) => break,
+            Err(PcapError::Incomplete(_)) => capture.refill()?,
+            Err(PcapError::BufferTooSmall) => {
+                if buffer_len >= MAX_READ_BUFFER_LEN {
+                    return Err(CaptureError::BlockTooLarge(MAX_READ_BUFFER_LEN));
+                }
+                buffer_len = (buffer_len * 2).min(MAX_READ_BUFFER_LEN);
+                capture.grow(buffer_len);
+                capture.refill()?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(timeline.events)
+}
+
+/// Convert a timestamp split in seconds and `resolution` units per second.
+fn timestamp(sec: u32, frac: u32, resolution: u64) -> Duration {
+    let nanos = frac as u64 * 1_000_000_000 / resolution;
+    Duration::from_secs(sec as u64) + Duration::from_nanos(nanos)
+}
+
+/// Decoding state for the whole capture.
+struct Timeline {
+    /// TCP ports known to belong to recsync servers.
+    ports: HashSet<u16>,
+    /// Connection number of each TCP connection, keyed by its lower address
+    /// first.
+    conns: HashMap<(SocketAddr, SocketAddr), usize>,
+    /// Reassembly state of each direction of each TCP connection.
+    streams: HashMap<(SocketAddr, SocketAddr), Stream>,
+    events: Vec<Event>,
+    last_ts: Duration,
+    /// Number of packets seen so far.
+    packets: usize,
+}
+
+impl Timeline {
+    fn new(ports: &[u16]) -> Timeline {
+        Timeline {
+            ports: ports.iter().copied().collect(),
+            conns: HashMap::new(),
+            streams: HashMap::new(),
+            events: Vec::new(),
+            last_ts: Duration::ZERO,
+            packets: 0,
+        }
+    }
+
+    /// Handle one captured link-layer frame. Anything that isn't UDP or TCP
+    /// over IP is ignored.
+    fn packet(
+        &mut self,
+        ts: Duration,
+        linktype: Linktype,
+        data: &[u8],
+    ) -> Result<(), CaptureError> {
+        self.last_ts = ts;
+        self.packets += 1;
+        let sliced = match linktype {
+            Linktype::ETHERNET => SlicedPacket::from_ethernet(data),
+            Linktype::LINUX_SLL => SlicedPacket::from_linux_sll(data),
+            Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => SlicedPacket::from_ip(data),
+            // BSD loopback, with a 4 byte address family header
+            Linktype::NULL | Linktype::LOOP => {
+                SlicedPacket::from_ip(data.get(4..).unwrap_or_default())
+            }
+            linktype => {
+                return Err(CaptureError::UnsupportedLinkType {
+                    packet: self.packets,
+                    linktype,
+                })
+            }
+        };
+        let packet = sliced.map_err(|source| CaptureError::Packet {
+            packet: self.packets,
+            source,
+        })?;
+
+        let (src_ip, dst_ip): (IpAddr, IpAddr) = match &packet.net {
+            Some(NetSlice::Ipv4(ip)) => (
+                ip.header().source_addr().into(),
+                ip.header().destination_addr().into(),
+            ),
+            Some(NetSlice::Ipv6(ip)) => (
+                ip.header().source_addr().into(),
+                ip.header().destination_addr().into(),
+            ),
+            _ => return Ok(()),
+        };
+
+        match &packet.transport {
+            Some(TransportSlice::Udp(udp)) => {
+                let src = SocketAddr::new(src_ip, udp.source_port());
+                let dst = SocketAddr::new(dst_ip, udp.destination_port());
+                if dst.port() == SERVER_ANNOUNCEMENT_UDP_PORT {
+                    self.announcement(ts, src, dst, udp.payload());
+                }
+            }
+            Some(TransportSlice::Tcp(tcp)) => {
+                let src = SocketAddr::new(src_ip, tcp.source_port());
+                let dst = SocketAddr::new(dst_ip, tcp.destination_port());
+                let segment = Segment {
+                    seq: tcp.sequence_number(),
+                    syn: tcp.syn(),
+                    payload: tcp.payload(),
+                };
+                self.segment(ts, src, dst, segment);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn announcement(&mut self, ts: Duration, src: SocketAddr, dst: SocketAddr, data: &[u8]) {
+        let kind = match Announcement::decode(data, src) {
+            Ok(announcement) => {
+                self.ports.insert(announcement.server_port);
+                EventKind::Announcement(announcement)
+            }
+            Err(err) => EventKind::InvalidAnnouncement(err),
+        };
+        self.events.push(Event { ts, src, dst, kind });
+    }
+
+    fn segment(&mut self, ts: Duration, src: SocketAddr, dst: SocketAddr, segment: Segment<'_>) {
+        if !self.streams.contains_key(&(src, dst)) {
+            let known_port = self.ports.contains(&src.port()) || self.ports.contains(&dst.port());
+            let has_magic = segment.payload.starts_with(&MSG_MAGIC_ID.to_be_bytes());
+            let conn_key = if src < dst { (src, dst) } else { (dst, src) };
+            let tracked = self.conns.contains_key(&conn_key);
+            if !(known_port || has_magic || tracked) {
+                return;
+            }
+            let next_conn = self.conns.len() + 1;
+            let conn = *self.conns.entry(conn_key).or_insert(next_conn);
+            self.streams.insert((src, dst), Stream::new(conn));
+        }
+
+        let stream = self.streams.get_mut(&(src, dst)).unwrap();
+        let conn = stream.conn;
+        let complete = stream.push(segment);
+        for result in stream.decode() {
+            let kind = match result {
+                Ok(msg) => EventKind::Message { conn, msg },
+                Err(err) => EventKind::DecodeError { conn, err },
+            };
+            self.events.push(Event { ts, src, dst, kind });
+        }
+        if !complete {
+            stream.abandon();
+            self.events.push(Event {
+                ts,
+                src,
+                dst,
+                kind: EventKind::StreamGap { conn },
+            });
+        }
+    }
+}
+
+/// The parts of a TCP segment needed for reassembly.
+struct Segment<'a> {
+    seq: u32,
+    syn: bool,
+    payload: &'a [u8],
+}
+
+/// Reassembly state of one direction of a TCP connection.
+struct Stream {
+    conn: usize,
+    /// Sequence number of the first payload byte, once known.
+    base: Option<u32>,
+    /// Offset from `base` of the next byte expected.
+    next: u32,
+    /// Segments received ahead of `next`, keyed by their offset from `base`.
+    pending: BTreeMap<u32, Vec<u8>>,
+    /// Total length of the segments in `pending`.
+    pending_len: usize,
+    /// Reassembled bytes not yet decoded.
+    buf: Vec<u8>,
+    /// Bytes of a bad frame still to be dropped.
+    skip: usize,
+    /// Framing was lost; the rest of the stream is ignored.
+    lost: bool,
+}
+
+impl Stream {
+    fn new(conn: usize) -> Stream {
+        Stream {
+            conn,
+            base: None,
+            next: 0,
+            pending: BTreeMap::new(),
+            pending_len: 0,
+            buf: Vec::new(),
+            skip: 0,
+            lost: false,
+        }
+    }
+
+    /// Add a segment, appending whatever is now in order to `buf`.
+    ///
+    /// Returns `false` if too much data is waiting behind a missing segment,
+    /// in which case the stream should be abandoned once `buf` is decoded.
+    fn push(&mut self, segment: Segment<'_>) -> bool {
+        if segment.syn {
+            // The SYN itself takes up one sequence number
+            self.base = Some(segment.seq.wrapping_add(1));
+            self.next = 0;
+            self.pending.clear();
+            self.pending_len = 0;
+            self.buf.clear();
+            self.skip = 0;
+            self.lost = false;
+        }
+        if self.lost || segment.payload.is_empty() {
+            return true;
+        }
+        // Capture started mid-connection, start from the first segment seen
+        let base = *self.base.get_or_insert(segment.seq);
+
+        let offset = segment.seq.wrapping_sub(base);
+        let end = offset.wrapping_add(segment.payload.len() as u32);
+        if end <= self.next {
+            // Retransmission of data already seen
+            return true;
+        }
+        let pending = self.pending.entry(offset).or_default();
+        if pending.len() < segment.payload.len() {
+            self.pending_len += segment.payload.len() - pending.len();
+            *pending = segment.payload.to_vec();
+        }
+
+        while let Some(entry) = self.pending.first_entry() {
+            let offset = *entry.key();
+            if offset > self.next {
+                break;
+            }
+            let data = entry.remove();
+            self.pending_len -= data.len();
+            let overlap = (self.next - offset) as usize;
+            if overlap < data.len() {
+                self.buf.extend_from_slice(&data[overlap..]);
+                self.next = offset.wrapping_add(data.len() as u32);
+            }
+        }
+
+        self.pending_len <= MAX_PENDING_LEN
+    }
+
+    /// Give up on the rest of the stream.
+    fn abandon(&mut self) {
+        self.lost = true;
+        self.pending.clear();
+        self.pending_len = 0;
+        self.buf.clear();
+    }
+
+    /// Decode every complete frame in `buf`.
+    fn decode(&mut self) -> Vec<Result<Message, Error>> {
+        let mut results = Vec::new();
+        let mut pos = 0;
+        loop {
+            let skipped = self.skip.min(self.buf.len() - pos);
+            pos += skipped;
+            self.skip -= skipped;
+            if self.lost || self.skip > 0 {
+                break;
+            }
+
+            match decode_from(&self.buf[pos..]) {
+                Ok(Some((msg, len))) => {
+                    results.push(Ok(msg));
+                    pos += len;
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    // Skip the bad frame if its header can still be trusted
+                    // to say where the next one starts
+                    match MessageHeader::peek(&self.buf[pos..]) {
+                        Some(header) if header.id == MSG_MAGIC_ID => {
+                            self.skip = HEADER_LEN.saturating_add(header.len as usize);
+                        }
+                        _ => self.lost = true,
+                    }
+                    results.push(Err(err));
+                }
+            }
+        }
+        if self.lost {
+            self.buf.clear();
+        } else {
+            self.buf.drain(..pos);
+        }
+        results
+    }
+}
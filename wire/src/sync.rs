@@ -0,0 +1,161 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+//! Blocking transport over `std::io` streams.
+//!
+//! `MessageReader` and `MessageWriter` frame messages over any `Read` or
+//! `Write`, such as a `std::net::TcpStream`, without an async runtime. They
+//! apply the same checks as `MessageCodec`.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::{
+    check_header, decode_from_with_max, encode_into, header::MessageHeader, Error, Message,
+    DEFAULT_MAX_FRAME_LEN, HEADER_LEN,
+};
+
+/// Reads messages from a blocking byte stream.
+///
+/// Only the bytes of one frame are read at a time, so nothing past the last
+/// message returned is taken from the stream. Messages of either direction
+/// are returned; convert them with `ClientMessage::try_from` or
+/// `ServerMessage::try_from` to enforce the side being read.
+#[derive(Debug)]
+pub struct MessageReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    max_frame: usize,
+}
+
+impl<R: Read> MessageReader<R> {
+    /// Create a reader that accepts frames up to `DEFAULT_MAX_FRAME_LEN` bytes.
+    pub fn new(inner: R) -> MessageReader<R> {
+        MessageReader::with_max_frame(inner, DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create a reader that rejects frames larger than `max_frame` bytes,
+    /// header included, before reading their body.
+    pub fn with_max_frame(inner: R, max_frame: usize) -> MessageReader<R> {
+        MessageReader {
+            inner,
+            buf: Vec::new(),
+            max_frame,
+        }
+    }
+
+    /// Read the next message, blocking until all of it has arrived.
+    ///
+    /// Returns `None` if the stream ends between two frames. A stream that
+    /// ends inside a frame is reported as an `UnexpectedEof` I/O error.
+    /// After any other error the stream may no longer be at a frame
+    /// boundary and should be closed.
+    pub fn read_message(&mut self) -> Result<Option<Message>, Error> {
+        let mut header = [0; HEADER_LEN];
+        if !read_header(&mut self.inner, &mut header)? {
+            return Ok(None);
+        }
+        let frame_len = check_header(&MessageHeader::from_bytes(&header), self.max_frame)?;
+
+        // Read the rest of the frame only once its header has been checked
+        self.buf.clear();
+        self.buf.extend_from_slice(&header);
+        self.buf.resize(frame_len, 0);
+        self.inner.read_exact(&mut self.buf[HEADER_LEN..])?;
+
+        Ok(decode_from_with_max(&self.buf, self.max_frame)?.map(|(msg, _)| msg))
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Return the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for MessageReader<R> {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+/// Fill `buf` with a header, returning `false` if the stream ended before
+/// its first byte.
+fn read_header(inner: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match inner.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+/// Writes messages to a blocking byte stream.
+///
+/// Each message is written with a single `write_all`. Wrap the stream in a
+/// `BufWriter` to batch many small messages, such as an upload, and call
+/// `flush` when done.
+#[derive(Debug)]
+pub struct MessageWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> MessageWriter<W> {
+    /// Create a writer over `inner`.
+    pub fn new(inner: W) -> MessageWriter<W> {
+        MessageWriter {
+            inner,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Encode and write `msg`. Nothing is written if the message fails
+    /// validation.
+    pub fn write_message(&mut self, msg: &Message) -> Result<(), Error> {
+        self.buf.clear();
+        encode_into(msg, &mut self.buf)?;
+        self.inner.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    /// Flush the underlying stream.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Return the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
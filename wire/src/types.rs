@@ -5,31 +5,43 @@
 // You must comply with both licenses to use, modify, or distribute this software.
 // See the LICENSE file for details.
 
-use std::net::Ipv4Addr;
+use crate::{Error, ProtocolVersion};
 
 /// AddRecord message type discriminant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
 pub enum AddRecordType {
     /// A regular PV record.
     Record = 0,
-    /// An alias for an existing record.
+    /// An alias for the record sent with the same `recid`.
     Alias = 1,
 }
 
-/// UDP Announcement message structure.
-#[derive(Debug)]
-pub struct Announcement {
-    /// Magic ID identifying this as a RecSync announcement.
-    pub id: u16,
-    /// IPv4 address of the announcing server.
-    pub server_addr: Ipv4Addr,
-    /// TCP port the server is listening on.
-    pub server_port: u16,
-    /// Server-generated session key.
-    pub server_key: u32,
+impl TryFrom<u8> for AddRecordType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AddRecordType::Record),
+            1 => Ok(AddRecordType::Alias),
+            _ => Err(Error::UnknownRecordType(value)),
+        }
+    }
+}
+
+impl From<AddRecordType> for u8 {
+    fn from(atype: AddRecordType) -> u8 {
+        match atype {
+            AddRecordType::Record => 0,
+            AddRecordType::Alias => 1,
+        }
+    }
 }
 
 /// Message type identifiers used in the wire protocol header.
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 pub enum MessageID {
     /// Server greeting sent after a client connects.
@@ -50,18 +62,20 @@ pub enum MessageID {
     AddInfo = 0x0006,
 }
 
-impl From<u16> for MessageID {
-    fn from(value: u16) -> Self {
+impl TryFrom<u16> for MessageID {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
-            0x8001 => MessageID::ServerGreet,
-            0x0001 => MessageID::ClientGreet,
-            0x8002 => MessageID::Ping,
-            0x0002 => MessageID::Pong,
-            0x0003 => MessageID::AddRecord,
-            0x0004 => MessageID::DelRecord,
-            0x0005 => MessageID::UploadDone,
-            0x0006 => MessageID::AddInfo,
-            _ => unimplemented!("Unknown Message ID"),
+            0x8001 => Ok(MessageID::ServerGreet),
+            0x0001 => Ok(MessageID::ClientGreet),
+            0x8002 => Ok(MessageID::Ping),
+            0x0002 => Ok(MessageID::Pong),
+            0x0003 => Ok(MessageID::AddRecord),
+            0x0004 => Ok(MessageID::DelRecord),
+            0x0005 => Ok(MessageID::UploadDone),
+            0x0006 => Ok(MessageID::AddInfo),
+            _ => Err(Error::UnknownMessageId(value)),
         }
     }
 }
@@ -83,12 +97,17 @@ impl From<MessageID> for u16 {
 
 // Define all the message structs and enums here
 
-/// Server greeting payload (no additional fields).
-#[derive(Debug, Clone, PartialEq)]
-pub struct ServerGreet;
+/// Server greeting payload.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerGreet {
+    /// Highest protocol version the server supports.
+    pub version: ProtocolVersion,
+}
 
 /// Keepalive ping payload.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ping {
     /// Random nonce that the client must echo back in the Pong.
     pub nonce: u32,
@@ -96,13 +115,27 @@ pub struct Ping {
 
 /// Client greeting payload.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClientGreet {
+    /// Protocol version chosen by the client for this connection.
+    pub version: ProtocolVersion,
     /// Server key received in the UDP announcement.
     pub serv_key: u32,
 }
 
+impl ClientGreet {
+    /// Create a greeting for the current protocol version.
+    pub fn new(serv_key: u32) -> ClientGreet {
+        ClientGreet {
+            version: ProtocolVersion::CURRENT,
+            serv_key,
+        }
+    }
+}
+
 /// Keepalive pong payload.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pong {
     /// Nonce copied from the corresponding Ping.
     pub nonce: u32,
@@ -110,23 +143,46 @@ pub struct Pong {
 
 /// Payload for registering a PV record or alias on the server.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AddRecord {
     /// Record identifier assigned by the client.
     pub recid: u32,
-    /// Record type discriminant (`AddRecordType::Record` or `AddRecordType::Alias`).
-    pub atype: u8,
-    /// Length of the record type string in bytes.
-    pub rtlen: u8,
-    /// Length of the record name string in bytes.
-    pub rnlen: u16,
-    /// Record type string (e.g. `"ai"`).
+    /// Whether this is a record or an alias of the record with the same `recid`.
+    pub atype: AddRecordType,
+    /// Record type string (e.g. `"ai"`), at most 255 bytes.
     pub rtype: String,
-    /// Record name or alias string.
+    /// Record name or alias string, at most 65535 bytes.
     pub rname: String,
 }
 
+impl AddRecord {
+    /// Create a new AddRecord payload, rejecting strings too long for their
+    /// length fields.
+    pub fn new(
+        recid: u32,
+        atype: AddRecordType,
+        rtype: String,
+        rname: String,
+    ) -> Result<AddRecord, Error> {
+        let msg = AddRecord {
+            recid,
+            atype,
+            rtype,
+            rname,
+        };
+        msg.check_lengths()?;
+        Ok(msg)
+    }
+
+    pub(crate) fn check_lengths(&self) -> Result<(), Error> {
+        check_field_len("rtype", self.rtype.len(), u8::MAX as usize)?;
+        check_field_len("rname", self.rname.len(), u16::MAX as usize)
+    }
+}
+
 /// Payload for removing a previously registered PV record.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DelRecord {
     /// Record identifier to remove.
     pub recid: u32,
@@ -134,25 +190,47 @@ pub struct DelRecord {
 
 /// Payload signalling that the client has finished uploading records.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UploadDone;
 
 /// Payload for attaching a key-value metadata entry to a record.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AddInfo {
     /// Record identifier this info belongs to (0 for client-level info).
     pub recid: u32,
-    /// Length of the key string in bytes.
-    pub keylen: u8,
-    /// Length of the value string in bytes.
-    pub valen: u16,
-    /// Metadata key.
+    /// Metadata key, at most 255 bytes.
     pub key: String,
-    /// Metadata value.
+    /// Metadata value, at most 65535 bytes.
     pub value: String,
 }
 
+impl AddInfo {
+    /// Create a new AddInfo payload, rejecting strings too long for their
+    /// length fields.
+    pub fn new(recid: u32, key: String, value: String) -> Result<AddInfo, Error> {
+        let msg = AddInfo { recid, key, value };
+        msg.check_lengths()?;
+        Ok(msg)
+    }
+
+    pub(crate) fn check_lengths(&self) -> Result<(), Error> {
+        check_field_len("key", self.key.len(), u8::MAX as usize)?;
+        check_field_len("value", self.value.len(), u16::MAX as usize)
+    }
+}
+
+fn check_field_len(field: &'static str, len: usize, max: usize) -> Result<(), Error> {
+    if len > max {
+        return Err(Error::FieldTooLong { field, len, max });
+    }
+    Ok(())
+}
+
 /// All messages that can be exchanged over the wire.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type"))]
 pub enum Message {
     /// Server greeting.
     ServerGreet(ServerGreet),
@@ -170,4 +248,130 @@ pub enum Message {
     UploadDone(UploadDone),
     /// Attach metadata to a record.
     AddInfo(AddInfo),
+    /// A message with an ID this implementation does not know about.
+    Unknown {
+        /// Message type identifier from the header.
+        msg_id: u16,
+        /// Raw message body.
+        body: Vec<u8>,
+    },
+}
+
+impl Message {
+    /// Message type identifier used in the header for this message.
+    pub fn msg_id(&self) -> u16 {
+        match self {
+            Message::ServerGreet(_) => MessageID::ServerGreet.into(),
+            Message::Ping(_) => MessageID::Ping.into(),
+            Message::ClientGreet(_) => MessageID::ClientGreet.into(),
+            Message::Pong(_) => MessageID::Pong.into(),
+            Message::AddRecord(_) => MessageID::AddRecord.into(),
+            Message::DelRecord(_) => MessageID::DelRecord.into(),
+            Message::UploadDone(_) => MessageID::UploadDone.into(),
+            Message::AddInfo(_) => MessageID::AddInfo.into(),
+            Message::Unknown { msg_id, .. } => *msg_id,
+        }
+    }
+}
+
+/// Messages sent by a client (RecCaster) to a server (RecCeiver).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type"))]
+pub enum ClientMessage {
+    /// Client greeting.
+    ClientGreet(ClientGreet),
+    /// Keepalive pong from the client.
+    Pong(Pong),
+    /// Add a PV record or alias.
+    AddRecord(AddRecord),
+    /// Remove a PV record.
+    DelRecord(DelRecord),
+    /// Signal end of record upload.
+    UploadDone(UploadDone),
+    /// Attach metadata to a record.
+    AddInfo(AddInfo),
+    /// A client message with an ID this implementation does not know about.
+    Unknown {
+        /// Message type identifier from the header.
+        msg_id: u16,
+        /// Raw message body.
+        body: Vec<u8>,
+    },
+}
+
+/// Messages sent by a server (RecCeiver) to a client (RecCaster).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type"))]
+pub enum ServerMessage {
+    /// Server greeting.
+    ServerGreet(ServerGreet),
+    /// Keepalive ping from the server.
+    Ping(Ping),
+    /// A server message with an ID this implementation does not know about.
+    Unknown {
+        /// Message type identifier from the header.
+        msg_id: u16,
+        /// Raw message body.
+        body: Vec<u8>,
+    },
+}
+
+impl From<ClientMessage> for Message {
+    fn from(msg: ClientMessage) -> Message {
+        match msg {
+            ClientMessage::ClientGreet(msg) => Message::ClientGreet(msg),
+            ClientMessage::Pong(msg) => Message::Pong(msg),
+            ClientMessage::AddRecord(msg) => Message::AddRecord(msg),
+            ClientMessage::DelRecord(msg) => Message::DelRecord(msg),
+            ClientMessage::UploadDone(msg) => Message::UploadDone(msg),
+            ClientMessage::AddInfo(msg) => Message::AddInfo(msg),
+            ClientMessage::Unknown { msg_id, body } => Message::Unknown { msg_id, body },
+        }
+    }
+}
+
+impl From<ServerMessage> for Message {
+    fn from(msg: ServerMessage) -> Message {
+        match msg {
+            ServerMessage::ServerGreet(msg) => Message::ServerGreet(msg),
+            ServerMessage::Ping(msg) => Message::Ping(msg),
+            ServerMessage::Unknown { msg_id, body } => Message::Unknown { msg_id, body },
+        }
+    }
+}
+
+/// Unknown messages are accepted in either direction, since only the peer
+/// knows what they mean.
+impl TryFrom<Message> for ClientMessage {
+    type Error = Error;
+
+    fn try_from(msg: Message) -> Result<Self, Self::Error> {
+        match msg {
+            Message::ClientGreet(msg) => Ok(ClientMessage::ClientGreet(msg)),
+            Message::Pong(msg) => Ok(ClientMessage::Pong(msg)),
+            Message::AddRecord(msg) => Ok(ClientMessage::AddRecord(msg)),
+            Message::DelRecord(msg) => Ok(ClientMessage::DelRecord(msg)),
+            Message::UploadDone(msg) => Ok(ClientMessage::UploadDone(msg)),
+            Message::AddInfo(msg) => Ok(ClientMessage::AddInfo(msg)),
+            Message::Unknown { msg_id, body } => Ok(ClientMessage::Unknown { msg_id, body }),
+            msg => Err(Error::UnexpectedMessage(msg.msg_id())),
+        }
+    }
+}
+
+/// Unknown messages are accepted in either direction, since only the peer
+/// knows what they mean.
+impl TryFrom<Message> for ServerMessage {
+    type Error = Error;
+
+    fn try_from(msg: Message) -> Result<Self, Self::Error> {
+        match msg {
+            Message::ServerGreet(msg) => Ok(ServerMessage::ServerGreet(msg)),
+            Message::Ping(msg) => Ok(ServerMessage::Ping(msg)),
+            Message::Unknown { msg_id, body } => Ok(ServerMessage::Unknown { msg_id, body }),
+            msg => Err(Error::UnexpectedMessage(msg.msg_id())),
+        }
+    }
 }
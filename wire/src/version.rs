@@ -0,0 +1,72 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use std::fmt;
+
+/// Version of the RecSync protocol, carried by announcements and by both
+/// greetings.
+///
+/// Each side advertises the highest version it supports and the connection
+/// uses the lower of the two, so a newer peer must keep speaking every older
+/// version. Announcements and greetings from newer servers are accepted
+/// rather than rejected, leaving the choice to `negotiate`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ProtocolVersion(u8);
+
+impl ProtocolVersion {
+    /// The original protocol, as spoken by the C RecCaster and RecCeiver.
+    pub const V0: ProtocolVersion = ProtocolVersion(0);
+
+    /// Highest version this implementation supports.
+    pub const CURRENT: ProtocolVersion = ProtocolVersion::V0;
+
+    /// Wrap a version number read from the wire.
+    pub const fn new(version: u8) -> ProtocolVersion {
+        ProtocolVersion(version)
+    }
+
+    /// Version number as sent on the wire.
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// Whether this implementation can speak this version.
+    pub fn is_supported(self) -> bool {
+        self <= ProtocolVersion::CURRENT
+    }
+
+    /// Choose the version to speak with a peer that supports up to `peer`.
+    pub fn negotiate(self, peer: ProtocolVersion) -> ProtocolVersion {
+        self.min(peer)
+    }
+}
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        ProtocolVersion::CURRENT
+    }
+}
+
+impl From<u8> for ProtocolVersion {
+    fn from(version: u8) -> Self {
+        ProtocolVersion(version)
+    }
+}
+
+impl From<ProtocolVersion> for u8 {
+    fn from(version: ProtocolVersion) -> u8 {
+        version.0
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,174 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+use std::str;
+
+use crate::{
+    AddInfo, AddRecord, AddRecordType, ClientGreet, DelRecord, Error, Message, Ping, Pong,
+    ServerGreet, UploadDone,
+};
+
+/// Borrowed view of an AddRecord payload.
+///
+/// The strings are slices of the frame they were decoded from and are only
+/// checked for valid UTF-8 when they are accessed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AddRecordRef<'a> {
+    /// Record identifier assigned by the client.
+    pub recid: u32,
+    /// Whether this is a record or an alias of the record with the same `recid`.
+    pub atype: AddRecordType,
+    pub(crate) rtype: &'a [u8],
+    pub(crate) rname: &'a [u8],
+}
+
+impl<'a> AddRecordRef<'a> {
+    /// Record type string (e.g. `"ai"`).
+    pub fn rtype(&self) -> Result<&'a str, Error> {
+        Ok(str::from_utf8(self.rtype)?)
+    }
+
+    /// Record name or alias string.
+    pub fn rname(&self) -> Result<&'a str, Error> {
+        Ok(str::from_utf8(self.rname)?)
+    }
+
+    /// Raw bytes of the record type string.
+    pub fn rtype_bytes(&self) -> &'a [u8] {
+        self.rtype
+    }
+
+    /// Raw bytes of the record name string.
+    pub fn rname_bytes(&self) -> &'a [u8] {
+        self.rname
+    }
+
+    /// Copy into an owned `AddRecord`.
+    pub fn into_owned(self) -> Result<AddRecord, Error> {
+        Ok(AddRecord {
+            recid: self.recid,
+            atype: self.atype,
+            rtype: self.rtype()?.to_string(),
+            rname: self.rname()?.to_string(),
+        })
+    }
+}
+
+/// Borrowed view of an AddInfo payload.
+///
+/// The strings are slices of the frame they were decoded from and are only
+/// checked for valid UTF-8 when they are accessed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AddInfoRef<'a> {
+    /// Record identifier this info belongs to (0 for client-level info).
+    pub recid: u32,
+    pub(crate) key: &'a [u8],
+    pub(crate) value: &'a [u8],
+}
+
+impl<'a> AddInfoRef<'a> {
+    /// Metadata key.
+    pub fn key(&self) -> Result<&'a str, Error> {
+        Ok(str::from_utf8(self.key)?)
+    }
+
+    /// Metadata value.
+    pub fn value(&self) -> Result<&'a str, Error> {
+        Ok(str::from_utf8(self.value)?)
+    }
+
+    /// Raw bytes of the metadata key.
+    pub fn key_bytes(&self) -> &'a [u8] {
+        self.key
+    }
+
+    /// Raw bytes of the metadata value.
+    pub fn value_bytes(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// Copy into an owned `AddInfo`.
+    pub fn into_owned(self) -> Result<AddInfo, Error> {
+        Ok(AddInfo {
+            recid: self.recid,
+            key: self.key()?.to_string(),
+            value: self.value()?.to_string(),
+        })
+    }
+}
+
+/// Borrowed view of a message, as returned by `decode_ref_from`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageRef<'a> {
+    /// Server greeting.
+    ServerGreet(ServerGreet),
+    /// Keepalive ping from the server.
+    Ping(Ping),
+    /// Client greeting.
+    ClientGreet(ClientGreet),
+    /// Keepalive pong from the client.
+    Pong(Pong),
+    /// Add a PV record or alias.
+    AddRecord(AddRecordRef<'a>),
+    /// Remove a PV record.
+    DelRecord(DelRecord),
+    /// Signal end of record upload.
+    UploadDone(UploadDone),
+    /// Attach metadata to a record.
+    AddInfo(AddInfoRef<'a>),
+    /// A message with an ID this implementation does not know about.
+    Unknown {
+        /// Message type identifier from the header.
+        msg_id: u16,
+        /// Raw message body.
+        body: &'a [u8],
+    },
+}
+
+impl MessageRef<'_> {
+    /// Copy into an owned `Message`, validating any string fields.
+    pub fn into_owned(self) -> Result<Message, Error> {
+        Ok(match self {
+            MessageRef::ServerGreet(msg) => Message::ServerGreet(msg),
+            MessageRef::Ping(msg) => Message::Ping(msg),
+            MessageRef::ClientGreet(msg) => Message::ClientGreet(msg),
+            MessageRef::Pong(msg) => Message::Pong(msg),
+            MessageRef::AddRecord(msg) => Message::AddRecord(msg.into_owned()?),
+            MessageRef::DelRecord(msg) => Message::DelRecord(msg),
+            MessageRef::UploadDone(msg) => Message::UploadDone(msg),
+            MessageRef::AddInfo(msg) => Message::AddInfo(msg.into_owned()?),
+            MessageRef::Unknown { msg_id, body } => Message::Unknown {
+                msg_id,
+                body: body.to_vec(),
+            },
+        })
+    }
+}
+
+impl TryFrom<AddRecordRef<'_>> for AddRecord {
+    type Error = Error;
+
+    fn try_from(msg: AddRecordRef<'_>) -> Result<Self, Self::Error> {
+        msg.into_owned()
+    }
+}
+
+impl TryFrom<AddInfoRef<'_>> for AddInfo {
+    type Error = Error;
+
+    fn try_from(msg: AddInfoRef<'_>) -> Result<Self, Self::Error> {
+        msg.into_owned()
+    }
+}
+
+impl TryFrom<MessageRef<'_>> for Message {
+    type Error = Error;
+
+    fn try_from(msg: MessageRef<'_>) -> Result<Self, Self::Error> {
+        msg.into_owned()
+    }
+}
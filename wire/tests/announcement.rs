@@ -0,0 +1,72 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use std::net::{Ipv4Addr, SocketAddr};
+use wire::{Announcement, Error, ProtocolVersion};
+
+fn src() -> SocketAddr {
+    "10.0.0.7:5049".parse().unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let ann = Announcement::new(Ipv4Addr::new(10, 0, 0, 1), 34567, 0xcafef00d);
+    let bytes = ann.encode();
+    assert_eq!(
+        &bytes[..],
+        b"RC\x00\x00\x0a\x00\x00\x01\x87\x07\x00\x00\xca\xfe\xf0\x0d"
+    );
+    assert_eq!(Announcement::decode(&bytes, src()).unwrap(), ann);
+}
+
+#[test]
+fn broadcast_address_uses_sender() {
+    let ann = Announcement::new(Ipv4Addr::BROADCAST, 34567, 1);
+    let decoded = Announcement::decode(&ann.encode(), src()).unwrap();
+    assert_eq!(decoded.server_addr, Ipv4Addr::new(10, 0, 0, 7));
+
+    let v6: SocketAddr = "[::1]:5049".parse().unwrap();
+    assert!(matches!(
+        Announcement::decode(&ann.encode(), v6),
+        Err(Error::UnsupportedAddress(_))
+    ));
+}
+
+#[test]
+fn invalid_announcements_are_rejected() {
+    let bytes = Announcement::new(Ipv4Addr::LOCALHOST, 1, 1).encode();
+    for len in 0..bytes.len() {
+        assert!(matches!(
+            Announcement::decode(&bytes[..len], src()),
+            Err(Error::Truncated { .. })
+        ));
+    }
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(matches!(
+        Announcement::decode(&bad_magic, src()),
+        Err(Error::BadMagic(_))
+    ));
+}
+
+#[test]
+fn newer_versions_are_accepted() {
+    let mut announcement = Announcement::new(Ipv4Addr::new(10, 0, 0, 5), 5050, 1);
+    announcement.version = ProtocolVersion::new(3);
+    let bytes = announcement.encode();
+    assert_eq!(bytes[2], 3);
+
+    let decoded = Announcement::decode(&bytes, src()).unwrap();
+    assert_eq!(decoded.version, ProtocolVersion::new(3));
+    assert!(!decoded.version.is_supported());
+    assert_eq!(
+        ProtocolVersion::CURRENT.negotiate(decoded.version),
+        ProtocolVersion::CURRENT
+    );
+}
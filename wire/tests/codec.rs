@@ -0,0 +1,444 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+#![cfg(feature = "tokio")]
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use wire::{
+    decode_ref_from, AddInfo, AddRecord, AddRecordType, ClientCodec, ClientGreet, ClientMessage,
+    DelRecord, Error, FrameCodec, Message, MessageCodec, MessageID, Ping, Pong, ServerCodec,
+    ServerGreet, ServerMessage, UploadDone, MSG_MAGIC_ID,
+};
+
+fn encode(msg: Message) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    MessageCodec::new().encode(msg, &mut buf).unwrap();
+    buf.to_vec()
+}
+
+fn round_trip(msg: Message) {
+    let mut buf = BytesMut::new();
+    MessageCodec::new().encode(msg.clone(), &mut buf).unwrap();
+    let decoded = MessageCodec::new().decode(&mut buf).unwrap();
+    assert_eq!(decoded, Some(msg));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn client_greet_round_trip() {
+    round_trip(Message::ClientGreet(ClientGreet::new(0xdeadbeef)));
+}
+
+#[test]
+fn pong_round_trip() {
+    round_trip(Message::Pong(Pong { nonce: 42 }));
+}
+
+#[test]
+fn add_record_round_trip() {
+    round_trip(Message::AddRecord(AddRecord {
+        recid: 100,
+        atype: AddRecordType::Record,
+        rtype: "ai".to_string(),
+        rname: "DEV:AI".to_string(),
+    }));
+    round_trip(Message::AddRecord(AddRecord {
+        recid: 100,
+        atype: AddRecordType::Alias,
+        rtype: "ai".to_string(),
+        rname: "DEV:ALIAS".to_string(),
+    }));
+}
+
+#[test]
+fn add_info_round_trip() {
+    round_trip(Message::AddInfo(AddInfo {
+        recid: 0,
+        key: "ENGINEER".to_string(),
+        value: "Rust Caster!".to_string(),
+    }));
+}
+
+#[test]
+fn upload_done_round_trip() {
+    round_trip(Message::UploadDone(UploadDone));
+}
+
+#[test]
+fn server_greet_round_trip() {
+    round_trip(Message::ServerGreet(ServerGreet::default()));
+}
+
+#[test]
+fn ping_round_trip() {
+    round_trip(Message::Ping(Ping { nonce: 0x01020304 }));
+}
+
+#[test]
+fn del_record_round_trip() {
+    round_trip(Message::DelRecord(DelRecord { recid: 100 }));
+}
+
+#[test]
+fn encoded_byte_layout() {
+    assert_eq!(
+        encode(Message::ServerGreet(ServerGreet::default())),
+        b"RC\x80\x01\x00\x00\x00\x01\x00"
+    );
+    assert_eq!(
+        encode(Message::Ping(Ping { nonce: 0x01020304 })),
+        b"RC\x80\x02\x00\x00\x00\x04\x01\x02\x03\x04"
+    );
+    assert_eq!(
+        encode(Message::ClientGreet(ClientGreet::new(0x01020304))),
+        b"RC\x00\x01\x00\x00\x00\x08\x00\x00\x00\x00\x01\x02\x03\x04"
+    );
+    assert_eq!(
+        encode(Message::Pong(Pong { nonce: 0x01020304 })),
+        b"RC\x00\x02\x00\x00\x00\x04\x01\x02\x03\x04"
+    );
+    assert_eq!(
+        encode(Message::AddRecord(AddRecord {
+            recid: 100,
+            atype: AddRecordType::Alias,
+            rtype: "ai".to_string(),
+            rname: "X".to_string(),
+        })),
+        b"RC\x00\x03\x00\x00\x00\x0b\x00\x00\x00\x64\x01\x02\x00\x01aiX"
+    );
+    assert_eq!(
+        encode(Message::DelRecord(DelRecord { recid: 100 })),
+        b"RC\x00\x04\x00\x00\x00\x04\x00\x00\x00\x64"
+    );
+    assert_eq!(
+        encode(Message::UploadDone(UploadDone)),
+        b"RC\x00\x05\x00\x00\x00\x04\x00\x00\x00\x00"
+    );
+    assert_eq!(
+        encode(Message::AddInfo(AddInfo {
+            recid: 100,
+            key: "k".to_string(),
+            value: "vv".to_string(),
+        })),
+        b"RC\x00\x06\x00\x00\x00\x0b\x00\x00\x00\x64\x01\x00\x00\x02kvv"
+    );
+}
+
+#[test]
+fn decode_del_record() {
+    let mut buf = BytesMut::new();
+    buf.put_u16(MSG_MAGIC_ID);
+    buf.put_u16(0x0004);
+    buf.put_u32(4);
+    buf.put_u32(123);
+    let decoded = MessageCodec::new().decode(&mut buf).unwrap();
+    assert_eq!(decoded, Some(Message::DelRecord(DelRecord { recid: 123 })));
+}
+
+#[test]
+fn add_record_length_mismatch_is_rejected() {
+    let mut buf = BytesMut::new();
+    buf.put_u16(MSG_MAGIC_ID);
+    buf.put_u16(0x0003);
+    buf.put_u32(8 + 2 + 3);
+    buf.put_u32(100);
+    buf.put_u8(0);
+    buf.put_u8(2);
+    buf.put_u16(4); // claims 4 bytes of name, only 3 follow
+    buf.put_slice(b"aiDEV");
+    assert!(matches!(
+        MessageCodec::new().decode(&mut buf),
+        Err(Error::LengthMismatch {
+            expected: 14,
+            actual: 13
+        })
+    ));
+}
+
+#[test]
+fn add_info_invalid_utf8_is_rejected() {
+    let mut buf = BytesMut::new();
+    buf.put_u16(MSG_MAGIC_ID);
+    buf.put_u16(0x0006);
+    buf.put_u32(8 + 1 + 1);
+    buf.put_u32(0);
+    buf.put_u8(1);
+    buf.put_u8(0);
+    buf.put_u16(1);
+    buf.put_slice(&[b'k', 0xff]);
+    assert!(matches!(
+        MessageCodec::new().decode(&mut buf),
+        Err(Error::InvalidUtf8(_))
+    ));
+}
+
+#[test]
+fn bad_magic_is_rejected() {
+    let mut buf = BytesMut::from(&b"XX\x80\x02\x00\x00\x00\x04\x00\x00\x00\x01"[..]);
+    assert!(matches!(
+        MessageCodec::new().decode(&mut buf),
+        Err(Error::BadMagic(0x5858))
+    ));
+}
+
+#[test]
+fn unknown_message_id_is_rejected() {
+    assert!(matches!(
+        MessageID::try_from(0x1234),
+        Err(Error::UnknownMessageId(0x1234))
+    ));
+}
+
+#[test]
+fn unknown_message_is_passed_through() {
+    let mut buf = BytesMut::from(&b"RC\x12\x34\x00\x00\x00\x02\xab\xcdRC"[..]);
+    let msg = Message::Unknown {
+        msg_id: 0x1234,
+        body: vec![0xab, 0xcd],
+    };
+    assert_eq!(
+        MessageCodec::new().decode(&mut buf).unwrap(),
+        Some(msg.clone())
+    );
+    assert_eq!(&buf[..], b"RC");
+    round_trip(msg);
+}
+
+#[test]
+fn trailing_fields_are_skipped() {
+    let mut buf = BytesMut::new();
+    buf.put_slice(b"RC\x80\x01\x00\x00\x00\x03\x00\xff\xff");
+    buf.put_slice(&encode(Message::Ping(Ping { nonce: 5 })));
+    buf.put_slice(b"RC\x00\x06\x00\x00\x00\x0c\x00\x00\x00\x64\x01\x00\x00\x02kvv\x00");
+    let mut codec = MessageCodec::new();
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Message::ServerGreet(ServerGreet::default()))
+    );
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Message::Ping(Ping { nonce: 5 }))
+    );
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Message::AddInfo(AddInfo {
+            recid: 100,
+            key: "k".to_string(),
+            value: "vv".to_string(),
+        }))
+    );
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn truncated_body_is_rejected() {
+    let mut buf = BytesMut::from(&b"RC\x80\x02\x00\x00\x00\x02\x00\x01"[..]);
+    assert!(matches!(
+        MessageCodec::new().decode(&mut buf),
+        Err(Error::Truncated {
+            expected: 4,
+            actual: 2
+        })
+    ));
+}
+
+fn all_messages() -> Vec<Message> {
+    vec![
+        Message::ServerGreet(ServerGreet::default()),
+        Message::ClientGreet(ClientGreet::new(7)),
+        Message::Ping(Ping { nonce: 1 }),
+        Message::Pong(Pong { nonce: 1 }),
+        Message::AddRecord(AddRecord {
+            recid: 100,
+            atype: AddRecordType::Record,
+            rtype: "ai".to_string(),
+            rname: "DEV:AI".to_string(),
+        }),
+        Message::AddInfo(AddInfo {
+            recid: 100,
+            key: "recordDesc".to_string(),
+            value: "desc".to_string(),
+        }),
+        Message::DelRecord(DelRecord { recid: 100 }),
+        Message::UploadDone(UploadDone),
+    ]
+}
+
+#[test]
+fn decode_one_byte_at_a_time() {
+    let messages = all_messages();
+    let stream: Vec<u8> = messages.iter().cloned().flat_map(encode).collect();
+
+    let mut codec = MessageCodec::new();
+    let mut buf = BytesMut::new();
+    let mut decoded = Vec::new();
+    for byte in stream {
+        buf.put_u8(byte);
+        while let Some(msg) = codec.decode(&mut buf).unwrap() {
+            decoded.push(msg);
+        }
+    }
+    assert_eq!(decoded, messages);
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn partial_frame_is_not_consumed() {
+    let frame = encode(Message::Ping(Ping { nonce: 9 }));
+    for split in 0..frame.len() {
+        let mut buf = BytesMut::from(&frame[..split]);
+        assert_eq!(MessageCodec::new().decode(&mut buf).unwrap(), None);
+        assert_eq!(&buf[..], &frame[..split]);
+    }
+}
+
+#[test]
+fn bad_magic_does_not_consume_input() {
+    let mut buf = BytesMut::from(&b"XXXX\x00\x00\x00\x00"[..]);
+    assert!(MessageCodec::new().decode(&mut buf).is_err());
+    assert_eq!(buf.len(), 8);
+}
+
+#[test]
+fn client_and_server_codecs_talk_to_each_other() {
+    let mut client = ClientCodec::default();
+    let mut server = ServerCodec::default();
+    let mut buf = BytesMut::new();
+
+    server
+        .encode(ServerMessage::Ping(Ping { nonce: 3 }), &mut buf)
+        .unwrap();
+    assert_eq!(
+        client.decode(&mut buf).unwrap(),
+        Some(ServerMessage::Ping(Ping { nonce: 3 }))
+    );
+
+    client
+        .encode(ClientMessage::Pong(Pong { nonce: 3 }), &mut buf)
+        .unwrap();
+    assert_eq!(
+        server.decode(&mut buf).unwrap(),
+        Some(ClientMessage::Pong(Pong { nonce: 3 }))
+    );
+}
+
+#[test]
+fn codecs_reject_messages_from_the_wrong_direction() {
+    let mut buf = BytesMut::from(&encode(Message::Pong(Pong { nonce: 1 }))[..]);
+    assert!(matches!(
+        ClientCodec::default().decode(&mut buf),
+        Err(Error::UnexpectedMessage(0x0002))
+    ));
+
+    let mut buf = BytesMut::from(&encode(Message::Ping(Ping { nonce: 1 }))[..]);
+    assert!(matches!(
+        ServerCodec::default().decode(&mut buf),
+        Err(Error::UnexpectedMessage(0x8002))
+    ));
+}
+
+#[test]
+fn constructors_reject_oversize_strings() {
+    assert!(AddRecord::new(1, AddRecordType::Record, "ai".into(), "DEV:AI".into()).is_ok());
+    assert!(matches!(
+        AddRecord::new(1, AddRecordType::Record, "x".repeat(256), "DEV:AI".into()),
+        Err(Error::FieldTooLong {
+            field: "rtype",
+            len: 256,
+            max: 255
+        })
+    ));
+    assert!(matches!(
+        AddRecord::new(1, AddRecordType::Alias, "ai".into(), "x".repeat(65536)),
+        Err(Error::FieldTooLong { field: "rname", .. })
+    ));
+    assert!(AddInfo::new(1, "x".repeat(255), "x".repeat(65535)).is_ok());
+    assert!(matches!(
+        AddInfo::new(1, "x".repeat(300), "value".into()),
+        Err(Error::FieldTooLong { field: "key", .. })
+    ));
+}
+
+#[test]
+fn encoder_rejects_oversize_strings() {
+    let mut buf = BytesMut::new();
+    let msg = Message::AddInfo(AddInfo {
+        recid: 1,
+        key: "x".repeat(300),
+        value: "value".into(),
+    });
+    assert!(matches!(
+        MessageCodec::new().encode(msg, &mut buf),
+        Err(Error::FieldTooLong { field: "key", .. })
+    ));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn frame_codec_yields_whole_frames() {
+    let messages = all_messages();
+    let mut buf = BytesMut::new();
+    for msg in &messages {
+        buf.put_slice(&encode(msg.clone()));
+    }
+    let mut codec = FrameCodec::default();
+    for msg in messages {
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        let (decoded, len) = decode_ref_from(&frame).unwrap().unwrap();
+        assert_eq!(len, frame.len());
+        assert_eq!(decoded.into_owned().unwrap(), msg);
+    }
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn oversize_frame_is_rejected_before_buffering() {
+    let mut codec = MessageCodec::with_max_frame(64);
+    let mut buf = BytesMut::from(&b"RC\x12\x34\xff\xff\xff\xff"[..]);
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(Error::FrameTooLarge {
+            len: 0x1_0000_0007,
+            max: 64
+        })
+    ));
+    assert!(buf.capacity() < 1024);
+
+    let mut buf = BytesMut::from(
+        &encode(Message::Unknown {
+            msg_id: 0x1234,
+            body: vec![0; 57],
+        })[..],
+    );
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(Error::FrameTooLarge { len: 65, max: 64 })
+    ));
+}
+
+#[test]
+fn string_messages_are_bounded_by_their_length_fields() {
+    let mut codec = MessageCodec::with_max_frame(usize::MAX);
+    let mut buf = BytesMut::from(&b"RC\x00\x03\x00\x01\x10\x08"[..]);
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(Error::FrameTooLarge {
+            len: 0x1_1010,
+            max: wire::DEFAULT_MAX_FRAME_LEN
+        })
+    ));
+
+    let largest = Message::AddInfo(AddInfo {
+        recid: 1,
+        key: "k".repeat(255),
+        value: "v".repeat(65535),
+    });
+    let mut buf = BytesMut::from(&encode(largest.clone())[..]);
+    assert_eq!(buf.len(), wire::DEFAULT_MAX_FRAME_LEN);
+    assert_eq!(MessageCodec::new().decode(&mut buf).unwrap(), Some(largest));
+}
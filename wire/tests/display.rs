@@ -0,0 +1,81 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use wire::{
+    dump, encode_into, AddInfo, AddRecord, AddRecordType, Message, Ping, ServerGreet, UploadDone,
+};
+
+#[test]
+fn messages_display_on_one_line() {
+    let record = AddRecord::new(100, AddRecordType::Alias, "ai".into(), "DEV:X".into()).unwrap();
+    assert_eq!(
+        Message::AddRecord(record).to_string(),
+        "AddRecord recid=100 alias type=ai name=DEV:X"
+    );
+    assert_eq!(
+        Message::AddInfo(AddInfo::new(0, "ENGINEER".into(), "A B".into()).unwrap()).to_string(),
+        "AddInfo recid=0 key=\"ENGINEER\" value=\"A B\""
+    );
+    assert_eq!(
+        Message::AddInfo(AddInfo::new(0, "a b=c".into(), "d".into()).unwrap()).to_string(),
+        "AddInfo recid=0 key=\"a b=c\" value=\"d\""
+    );
+    assert_eq!(Message::Ping(Ping { nonce: 7 }).to_string(), "Ping nonce=7");
+    assert_eq!(
+        Message::Unknown {
+            msg_id: 0x8010,
+            body: vec![1, 2, 3]
+        }
+        .to_string(),
+        "Unknown msg_id=0x8010 len=3"
+    );
+}
+
+#[test]
+fn annotate_labels_fields_and_padding() {
+    let mut buf = Vec::new();
+    encode_into(&Message::ServerGreet(ServerGreet::default()), &mut buf).unwrap();
+    encode_into(
+        &Message::AddInfo(AddInfo::new(100, "k".into(), "vv".into()).unwrap()),
+        &mut buf,
+    )
+    .unwrap();
+    encode_into(&Message::UploadDone(UploadDone), &mut buf).unwrap();
+
+    let expected = "\
+0000  52 43                                            magic \"RC\"
+0002  80 01                                            msg_id ServerGreet
+0004  00 00 00 01                                      len 1
+0008  00                                               version 0
+      => ServerGreet version=0
+0009  52 43                                            magic \"RC\"
+000b  00 06                                            msg_id AddInfo
+000d  00 00 00 0b                                      len 11
+0011  00 00 00 64                                      recid 100
+0015  01                                               keylen 1
+0016  00                                               padding
+0017  00 02                                            valen 2
+0019  6b                                               key \"k\"
+001a  76 76                                            value \"vv\"
+      => AddInfo recid=100 key=\"k\" value=\"vv\"
+001c  52 43                                            magic \"RC\"
+001e  00 05                                            msg_id UploadDone
+0020  00 00 00 04                                      len 4
+0024  00 00 00 00                                      padding
+      => UploadDone
+";
+    assert_eq!(dump::annotate(&buf), expected);
+}
+
+#[test]
+fn annotate_stops_at_bad_magic() {
+    let dump = dump::annotate(b"XY\x00\x02\x00\x00\x00\x04\x00\x00\x00\x01");
+    assert!(dump.starts_with("0000  58 59"));
+    assert!(dump.contains("bad magic 0x5859"));
+    assert!(dump.contains("not followed"));
+}
@@ -0,0 +1,151 @@
+#!/usr/bin/env python3
+# This file is part of Recsync-rs.
+# Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+#
+# This project is licensed under both the MIT License and the BSD 3-Clause License.
+# You must comply with both licenses to use, modify, or distribute this software.
+# See the LICENSE file for details.
+"""Regenerate the conformance fixtures used by wire/tests/conformance.rs.
+
+The message bodies are packed with the struct definitions of
+recceiver/recast.py for the TCP messages and of recceiver/announce.py for the
+UDP announcement, imported from a checkout of
+https://github.com/ChannelFinder/recsync. The message ids and the UploadDone
+body are still restated here, as recast.py has no definitions for them. The
+commit of the checkout is written to SOURCE next to the fixtures.
+
+Needs the recceiver dependencies (twisted) to import. Run from this
+directory:
+
+    python3 generate.py /path/to/recsync
+"""
+
+import importlib
+import os
+import subprocess
+import sys
+
+if len(sys.argv) != 2:
+    sys.exit("usage: generate.py RECSYNC_CHECKOUT")
+CHECKOUT = sys.argv[1]
+sys.path.insert(0, os.path.join(CHECKOUT, "server"))
+
+recast = importlib.import_module("recceiver.recast")
+announce = importlib.import_module("recceiver.announce")
+
+
+def upstream(module, name):
+    try:
+        return getattr(module, name)
+    except AttributeError:
+        sys.exit(f"{module.__name__} has no {name}; update generate.py for this version")
+
+
+_M = upstream(recast, "_M")
+_Head = upstream(recast, "_Head")
+_s_greet = upstream(recast, "_s_greet")
+_c_greet = upstream(recast, "_c_greet")
+_ping = upstream(recast, "_ping")
+_c_rec = upstream(recast, "_c_rec")
+_c_info = upstream(recast, "_c_info")
+_c_del = upstream(recast, "_c_del")
+_Ann = upstream(announce, "_Ann")
+
+
+def frame(msg_id, body):
+    return _Head.pack(_M, msg_id, len(body)) + body
+
+
+def server_greet():
+    return frame(0x8001, _s_greet.pack(0))
+
+
+def ping(nonce):
+    return frame(0x8002, _ping.pack(nonce))
+
+
+def client_greet(key):
+    # version 0, client type 0, two bytes of padding
+    return frame(0x0001, _c_greet.pack(0, 0, key))
+
+
+def pong(nonce):
+    return frame(0x0002, _ping.pack(nonce))
+
+
+def add_record(recid, atype, rtype, rname):
+    rtype, rname = rtype.encode(), rname.encode()
+    return frame(
+        0x0003, _c_rec.pack(recid, atype, len(rtype), len(rname)) + rtype + rname
+    )
+
+
+def del_record(recid):
+    return frame(0x0004, _c_del.pack(recid))
+
+
+def upload_done():
+    # The one body not taken from upstream: recast.py has no struct for it
+    return frame(0x0005, b"\0\0\0\0")
+
+
+def add_info(recid, key, value):
+    key, value = key.encode(), value.encode()
+    return frame(0x0006, _c_info.pack(recid, len(key), len(value)) + key + value)
+
+
+def announcement(addr, port, key):
+    return _Ann.pack(_M, 0, bytes(addr), port, 0, key)
+
+
+FIXTURES = {
+    "server_greet.bin": server_greet(),
+    "ping.bin": ping(0x12345678),
+    "client_greet.bin": client_greet(0xDEADBEEF),
+    "pong.bin": pong(0x12345678),
+    "add_record.bin": add_record(100, 0, "ai", "DEV:AI"),
+    "add_record_alias.bin": add_record(100, 1, "ai", "DEV:ALIAS"),
+    "del_record.bin": del_record(100),
+    "upload_done.bin": upload_done(),
+    "add_info.bin": add_info(100, "recordDesc", "Temperature, °C"),
+    "announcement.bin": announcement((192, 168, 1, 10), 5050, 0xCAFEF00D),
+    # Server side of a session: greeting and one keepalive
+    "session_server.bin": server_greet() + ping(7),
+    # Client side of the same session: greeting, a full upload, the reply to
+    # the ping and a record removed after the upload
+    "session_client.bin": b"".join(
+        [
+            client_greet(0xCAFEF00D),
+            add_info(0, "ENGINEER", "Jane Doe"),
+            add_info(0, "HOSTNAME", "ioc01"),
+            add_record(100, 0, "ai", "DEV:TEMP"),
+            add_record(100, 1, "ai", "DEV:TEMP:ALIAS"),
+            add_info(100, "recordDesc", "Temperature"),
+            add_record(101, 0, "longout", "DEV:SETPOINT"),
+            upload_done(),
+            pong(7),
+            del_record(101),
+        ]
+    ),
+}
+
+def source():
+    commit = subprocess.run(
+        ["git", "-C", CHECKOUT, "describe", "--always", "--dirty", "--tags"],
+        capture_output=True,
+        text=True,
+        check=True,
+    ).stdout.strip()
+    return (
+        "Generated by generate.py from the struct definitions of\n"
+        "recceiver/recast.py and recceiver/announce.py in\n"
+        f"https://github.com/ChannelFinder/recsync at {commit}\n"
+    )
+
+
+if __name__ == "__main__":
+    for name, data in FIXTURES.items():
+        with open(name, "wb") as f:
+            f.write(data)
+    with open("SOURCE", "w") as f:
+        f.write(source())
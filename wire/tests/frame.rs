@@ -0,0 +1,57 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use wire::{decode_from, encode_into, AddRecord, AddRecordType, Error, Message, Ping, UploadDone};
+
+#[test]
+fn encode_into_vec_and_decode_from_slice() {
+    let messages = vec![
+        Message::Ping(Ping { nonce: 11 }),
+        Message::AddRecord(
+            AddRecord::new(100, AddRecordType::Record, "ai".into(), "DEV:AI".into()).unwrap(),
+        ),
+        Message::UploadDone(UploadDone),
+    ];
+    let mut buf = Vec::new();
+    for msg in &messages {
+        encode_into(msg, &mut buf).unwrap();
+    }
+
+    let mut decoded = Vec::new();
+    let mut pos = 0;
+    while let Some((msg, len)) = decode_from(&buf[pos..]).unwrap() {
+        decoded.push(msg);
+        pos += len;
+    }
+    assert_eq!(decoded, messages);
+    assert_eq!(pos, buf.len());
+}
+
+#[test]
+fn decode_from_waits_for_complete_frame() {
+    let mut buf = Vec::new();
+    encode_into(&Message::Ping(Ping { nonce: 11 }), &mut buf).unwrap();
+    for split in 0..buf.len() {
+        assert_eq!(decode_from(&buf[..split]).unwrap(), None);
+    }
+}
+
+#[test]
+fn unknown_record_type_is_rejected() {
+    let mut buf = Vec::new();
+    let record = AddRecord::new(100, AddRecordType::Alias, "ai".into(), "DEV:AI".into()).unwrap();
+    encode_into(&Message::AddRecord(record), &mut buf).unwrap();
+    assert_eq!(buf[12], 1);
+    buf[12] = 2;
+    assert!(matches!(
+        decode_from(&buf),
+        Err(Error::UnknownRecordType(2))
+    ));
+    assert_eq!(AddRecordType::try_from(0).unwrap(), AddRecordType::Record);
+    assert_eq!(u8::from(AddRecordType::Alias), 1);
+}
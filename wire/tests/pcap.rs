@@ -0,0 +1,287 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+#![cfg(feature = "pcap")]
+use etherparse::PacketBuilder;
+use std::{io::Cursor, net::Ipv4Addr, time::Duration};
+use wire::{
+    encode_into,
+    pcap::{read_capture, CaptureError, Event, EventKind},
+    AddInfo, AddRecord, AddRecordType, Announcement, ClientGreet, Error, Message, Ping,
+    ServerGreet, UploadDone,
+};
+
+const CLIENT: [u8; 4] = [10, 0, 0, 7];
+const SERVER: [u8; 4] = [10, 0, 0, 5];
+const CLIENT_PORT: u16 = 51234;
+const SERVER_PORT: u16 = 40000;
+
+fn encode(msgs: &[Message]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for msg in msgs {
+        encode_into(msg, &mut buf).unwrap();
+    }
+    buf
+}
+
+fn udp(src: [u8; 4], dst: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::new();
+    PacketBuilder::ethernet2([1; 6], [2; 6])
+        .ipv4(src, dst, 64)
+        .udp(5049, 5049)
+        .write(&mut packet, payload)
+        .unwrap();
+    packet
+}
+
+fn tcp(to_server: bool, seq: u32, syn: bool, payload: &[u8]) -> Vec<u8> {
+    let (src, dst, sport, dport) = if to_server {
+        (CLIENT, SERVER, CLIENT_PORT, SERVER_PORT)
+    } else {
+        (SERVER, CLIENT, SERVER_PORT, CLIENT_PORT)
+    };
+    let builder = PacketBuilder::ethernet2([1; 6], [2; 6])
+        .ipv4(src, dst, 64)
+        .tcp(sport, dport, seq, 1024);
+    let builder = if syn { builder.syn() } else { builder };
+    let mut packet = Vec::new();
+    builder.write(&mut packet, payload).unwrap();
+    packet
+}
+
+/// Build a microsecond pcap file of Ethernet frames, one per second.
+fn pcap(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut file = Vec::new();
+    file.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+    file.extend_from_slice(&2u16.to_le_bytes());
+    file.extend_from_slice(&4u16.to_le_bytes());
+    file.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+    file.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+    file.extend_from_slice(&262144u32.to_le_bytes()); // snaplen
+    file.extend_from_slice(&1u32.to_le_bytes()); // Ethernet
+    for (i, packet) in packets.iter().enumerate() {
+        file.extend_from_slice(&(i as u32).to_le_bytes());
+        file.extend_from_slice(&500u32.to_le_bytes());
+        file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        file.extend_from_slice(packet);
+    }
+    file
+}
+
+fn messages(events: &[Event]) -> Vec<(usize, &Message)> {
+    events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            EventKind::Message { conn, msg } => Some((*conn, msg)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn session_is_reassembled_from_split_and_reordered_segments() {
+    let announcement = Announcement::new(Ipv4Addr::BROADCAST, SERVER_PORT, 0xdeadbeef).encode();
+    let greet = encode(&[Message::ServerGreet(ServerGreet::default())]);
+    let upload = encode(&[
+        Message::ClientGreet(ClientGreet::new(0xdeadbeef)),
+        Message::AddRecord(
+            AddRecord::new(100, AddRecordType::Record, "ai".into(), "DEV:X".into()).unwrap(),
+        ),
+        Message::AddInfo(AddInfo::new(100, "EGU".into(), "mm".into()).unwrap()),
+        Message::UploadDone(UploadDone),
+    ]);
+    let (a, rest) = upload.split_at(13);
+    let (b, c) = rest.split_at(20);
+
+    let file = pcap(&[
+        udp(SERVER, [255; 4], &announcement),
+        tcp(true, 999, true, &[]),
+        tcp(false, 4999, true, &[]),
+        tcp(false, 5000, false, &greet),
+        tcp(true, 1000, false, a),
+        // Out of order, then a retransmission of the first segment
+        tcp(true, 1000 + 33, false, c),
+        tcp(true, 1000, false, a),
+        tcp(true, 1000 + 13, false, b),
+    ]);
+    let events = read_capture(Cursor::new(file), &[]).unwrap();
+
+    match &events[0].kind {
+        EventKind::Announcement(announcement) => {
+            assert_eq!(announcement.server_addr, Ipv4Addr::from(SERVER));
+            assert_eq!(announcement.server_port, SERVER_PORT);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    let msgs: Vec<String> = messages(&events)
+        .iter()
+        .map(|(conn, msg)| format!("#{} {}", conn, msg))
+        .collect();
+    assert_eq!(
+        msgs,
+        [
+            "#1 ServerGreet version=0",
+            "#1 ClientGreet version=0 key=0xdeadbeef",
+            "#1 AddRecord recid=100 record type=ai name=DEV:X",
+            "#1 AddInfo recid=100 key=\"EGU\" value=\"mm\"",
+            "#1 UploadDone",
+        ]
+    );
+    // The upload completed when the missing middle segment arrived
+    assert_eq!(events.last().unwrap().ts, Duration::new(7, 500_000));
+}
+
+#[test]
+fn bad_frame_is_flagged_and_skipped() {
+    let mut upload = encode(&[Message::AddInfo(
+        AddInfo::new(1, "K".into(), "V".into()).unwrap(),
+    )]);
+    // Declare a value longer than the body
+    upload[15] = 9;
+    upload.extend(encode(&[Message::UploadDone(UploadDone)]));
+
+    let file = pcap(&[tcp(true, 1, false, &upload)]);
+    let events = read_capture(Cursor::new(file), &[SERVER_PORT]).unwrap();
+
+    assert_eq!(events.len(), 2);
+    assert!(matches!(
+        events[0].kind,
+        EventKind::DecodeError {
+            conn: 1,
+            err: Error::LengthMismatch { .. }
+        }
+    ));
+    assert!(matches!(
+        events[1].kind,
+        EventKind::Message {
+            conn: 1,
+            msg: Message::UploadDone(_)
+        }
+    ));
+}
+
+#[test]
+fn unrelated_traffic_is_ignored() {
+    let file = pcap(&[tcp(true, 1, false, b"GET / HTTP/1.1\r\n\r\n")]);
+    assert!(read_capture(Cursor::new(file), &[]).unwrap().is_empty());
+}
+
+#[test]
+fn capture_errors_are_typed() {
+    let file = pcap(&[udp(SERVER, [255; 4], &[0; 16])]);
+    let truncated = file[..file.len() - 3].to_vec();
+    assert!(matches!(
+        read_capture(Cursor::new(truncated), &[]),
+        Err(CaptureError::Truncated)
+    ));
+
+    assert!(matches!(
+        read_capture(Cursor::new(b"not a capture at all".to_vec()), &[]),
+        Err(CaptureError::Parse(_))
+    ));
+
+    // An IPv4 header that claims more bytes than the frame holds
+    let mut bad = udp(SERVER, [255; 4], &[0; 16]);
+    bad[17] = 200;
+    let file = pcap(&[udp(SERVER, [255; 4], &[0; 16]), bad]);
+    assert!(matches!(
+        read_capture(Cursor::new(file), &[]),
+        Err(CaptureError::Packet { packet: 2, .. })
+    ));
+}
+
+#[test]
+fn lost_segment_abandons_the_stream() {
+    let greet = encode(&[Message::ServerGreet(ServerGreet::default())]);
+    let mut packets = vec![tcp(false, 1, false, &greet)];
+    // The next 100 bytes were not captured, and 4 MiB more pile up behind them
+    let chunk = vec![0; 60000];
+    let mut seq = 1 + greet.len() as u32 + 100;
+    for _ in 0..80 {
+        packets.push(tcp(false, seq, false, &chunk));
+        seq += chunk.len() as u32;
+    }
+    let events = read_capture(Cursor::new(pcap(&packets)), &[SERVER_PORT]).unwrap();
+
+    assert_eq!(events.len(), 2);
+    assert!(matches!(
+        events[0].kind,
+        EventKind::Message {
+            msg: Message::ServerGreet(_),
+            ..
+        }
+    ));
+    assert!(matches!(events[1].kind, EventKind::StreamGap { conn: 1 }));
+}
+
+#[test]
+fn frames_larger_than_the_read_buffer_are_decoded() {
+    // One 65 KiB segment, as captured before TCP segmentation offload
+    let pings: Vec<Message> = (0..5457)
+        .map(|nonce| Message::Ping(Ping { nonce }))
+        .collect();
+    let packet = tcp(false, 1, false, &encode(&pings));
+    assert!(packet.len() > 65536);
+    let events = read_capture(Cursor::new(pcap(&[packet])), &[SERVER_PORT]).unwrap();
+
+    let msgs = messages(&events);
+    assert_eq!(msgs.len(), 5457);
+    assert!(matches!(msgs[5456], (1, Message::Ping(ping)) if ping.nonce == 5456));
+}
+
+#[test]
+fn pcapng_is_supported() {
+    fn block(file: &mut Vec<u8>, kind: u32, body: &[u8]) {
+        let len = 12 + body.len().div_ceil(4) * 4;
+        file.extend_from_slice(&kind.to_le_bytes());
+        file.extend_from_slice(&(len as u32).to_le_bytes());
+        file.extend_from_slice(body);
+        file.resize(file.len() + (len - 12 - body.len()), 0);
+        file.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    let packet = tcp(
+        false,
+        1,
+        false,
+        &encode(&[Message::ServerGreet(ServerGreet::default())]),
+    );
+    let mut file = Vec::new();
+    let mut shb = Vec::new();
+    shb.extend_from_slice(&0x1a2b3c4du32.to_le_bytes());
+    shb.extend_from_slice(&1u16.to_le_bytes());
+    shb.extend_from_slice(&0u16.to_le_bytes());
+    shb.extend_from_slice(&(-1i64).to_le_bytes());
+    block(&mut file, 0x0a0d0d0a, &shb);
+    let mut idb = Vec::new();
+    idb.extend_from_slice(&1u16.to_le_bytes()); // Ethernet
+    idb.extend_from_slice(&0u16.to_le_bytes());
+    idb.extend_from_slice(&65535u32.to_le_bytes());
+    block(&mut file, 1, &idb);
+    let mut epb = Vec::new();
+    epb.extend_from_slice(&0u32.to_le_bytes()); // Interface
+    epb.extend_from_slice(&0u32.to_le_bytes()); // Timestamp, microseconds
+    epb.extend_from_slice(&2_500_000u32.to_le_bytes());
+    epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    epb.extend_from_slice(&packet);
+    block(&mut file, 6, &epb);
+
+    let events = read_capture(Cursor::new(file), &[]).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ts, Duration::new(2, 500_000_000));
+    assert_eq!(events[0].src.to_string(), "10.0.0.5:40000");
+    assert!(matches!(
+        events[0].kind,
+        EventKind::Message {
+            msg: Message::ServerGreet(_),
+            ..
+        }
+    ));
+}
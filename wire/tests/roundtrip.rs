@@ -0,0 +1,151 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use proptest::prelude::*;
+use std::net::{Ipv4Addr, SocketAddr};
+use wire::{
+    decode_from, decode_ref_from, encode_into, AddInfo, AddRecord, AddRecordType, Announcement,
+    ClientGreet, DelRecord, Message, MessageID, Ping, Pong, ServerGreet, UploadDone,
+};
+
+/// A string of at most `max` bytes, including multi-byte characters.
+fn string(max: usize) -> impl Strategy<Value = String> {
+    proptest::collection::vec(any::<char>(), 0..=max / 4)
+        .prop_map(|chars| chars.into_iter().collect())
+}
+
+fn atype() -> impl Strategy<Value = AddRecordType> {
+    prop_oneof![Just(AddRecordType::Record), Just(AddRecordType::Alias)]
+}
+
+fn message() -> impl Strategy<Value = Message> {
+    prop_oneof![
+        any::<u8>().prop_map(|version| Message::ServerGreet(ServerGreet {
+            version: version.into()
+        })),
+        any::<u32>().prop_map(|nonce| Message::Ping(Ping { nonce })),
+        (any::<u8>(), any::<u32>()).prop_map(|(version, serv_key)| Message::ClientGreet(
+            ClientGreet {
+                version: version.into(),
+                serv_key,
+            }
+        )),
+        any::<u32>().prop_map(|nonce| Message::Pong(Pong { nonce })),
+        (any::<u32>(), atype(), string(255), string(1024)).prop_map(
+            |(recid, atype, rtype, rname)| Message::AddRecord(AddRecord {
+                recid,
+                atype,
+                rtype,
+                rname,
+            })
+        ),
+        any::<u32>().prop_map(|recid| Message::DelRecord(DelRecord { recid })),
+        Just(Message::UploadDone(UploadDone)),
+        (any::<u32>(), string(255), string(1024))
+            .prop_map(|(recid, key, value)| Message::AddInfo(AddInfo { recid, key, value })),
+        (
+            any::<u16>().prop_filter("unknown id", |id| MessageID::try_from(*id).is_err()),
+            proptest::collection::vec(any::<u8>(), 0..64)
+        )
+            .prop_map(|(msg_id, body)| Message::Unknown { msg_id, body }),
+    ]
+}
+
+fn encode(msgs: &[Message]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for msg in msgs {
+        encode_into(msg, &mut buf).unwrap();
+    }
+    buf
+}
+
+proptest! {
+    #[test]
+    fn encode_then_decode_is_identity(msg in message()) {
+        let buf = encode(std::slice::from_ref(&msg));
+        let (decoded, len) = decode_from(&buf).unwrap().unwrap();
+        prop_assert_eq!(&decoded, &msg);
+        prop_assert_eq!(len, buf.len());
+
+        let (view, len) = decode_ref_from(&buf).unwrap().unwrap();
+        prop_assert_eq!(view.into_owned().unwrap(), msg);
+        prop_assert_eq!(len, buf.len());
+    }
+
+    #[test]
+    fn incomplete_frames_wait_for_more_data(msg in message(), cut in any::<prop::sample::Index>()) {
+        let buf = encode(&[msg]);
+        let cut = cut.index(buf.len());
+        prop_assert!(decode_from(&buf[..cut]).unwrap().is_none());
+    }
+
+    #[test]
+    fn split_stream_decodes_every_frame(
+        msgs in proptest::collection::vec(message(), 1..8),
+        cuts in proptest::collection::vec(any::<prop::sample::Index>(), 0..8),
+    ) {
+        let stream = encode(&msgs);
+        let mut cuts: Vec<usize> = cuts.iter().map(|cut| cut.index(stream.len())).collect();
+        cuts.push(stream.len());
+        cuts.sort();
+
+        // Feed the stream in chunks, decoding whatever is complete after each
+        let mut buf = Vec::new();
+        let mut decoded = Vec::new();
+        let mut start = 0;
+        for cut in cuts {
+            buf.extend_from_slice(&stream[start..cut]);
+            start = cut;
+            while let Some((msg, len)) = decode_from(&buf).unwrap() {
+                decoded.push(msg);
+                buf.drain(..len);
+            }
+        }
+        prop_assert!(buf.is_empty());
+        prop_assert_eq!(decoded, msgs);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn codec_decodes_split_stream(
+        msgs in proptest::collection::vec(message(), 1..8),
+        chunk in 1usize..32,
+    ) {
+        use bytes::BytesMut;
+        use tokio_util::codec::Decoder;
+
+        let stream = encode(&msgs);
+        let mut codec = wire::MessageCodec::new();
+        let mut buf = BytesMut::new();
+        let mut decoded = Vec::new();
+        for bytes in stream.chunks(chunk) {
+            buf.extend_from_slice(bytes);
+            while let Some(msg) = codec.decode(&mut buf).unwrap() {
+                decoded.push(msg);
+            }
+        }
+        prop_assert!(buf.is_empty());
+        prop_assert_eq!(decoded, msgs);
+    }
+
+    #[test]
+    fn decoding_arbitrary_bytes_never_panics(data in proptest::collection::vec(any::<u8>(), 0..256)) {
+        let _ = decode_from(&data);
+        let _ = decode_ref_from(&data);
+        let _ = Announcement::decode(&data, SocketAddr::from(([10, 0, 0, 1], 5049)));
+    }
+
+    #[test]
+    fn announcement_round_trip(addr in any::<u32>(), port in any::<u16>(), key in any::<u32>()) {
+        let addr = Ipv4Addr::from(addr);
+        prop_assume!(!addr.is_broadcast());
+        let announcement = Announcement::new(addr, port, key);
+        let src = SocketAddr::from(([10, 0, 0, 1], 5049));
+        prop_assert_eq!(Announcement::decode(&announcement.encode(), src).unwrap(), announcement);
+    }
+}
@@ -0,0 +1,76 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+#![cfg(feature = "serde")]
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wire::{
+    AddInfo, AddRecord, AddRecordType, Announcement, ClientGreet, DelRecord, Message,
+    MessageHeader, Ping, Pong, ServerGreet, UploadDone,
+};
+
+#[test]
+fn messages_use_a_tagged_representation() {
+    let msg = Message::AddRecord(
+        AddRecord::new(100, AddRecordType::Alias, "ai".into(), "DEV:X".into()).unwrap(),
+    );
+    assert_eq!(
+        serde_json::to_value(&msg).unwrap(),
+        json!({"type": "AddRecord", "recid": 100, "atype": "Alias", "rtype": "ai", "rname": "DEV:X"})
+    );
+    assert_eq!(
+        serde_json::to_value(Message::ServerGreet(ServerGreet::default())).unwrap(),
+        json!({"type": "ServerGreet", "version": 0})
+    );
+    assert_eq!(
+        serde_json::to_value(Message::Unknown {
+            msg_id: 0x1234,
+            body: vec![1, 2]
+        })
+        .unwrap(),
+        json!({"type": "Unknown", "msg_id": 0x1234, "body": [1, 2]})
+    );
+}
+
+#[test]
+fn messages_round_trip_through_json() {
+    let messages = vec![
+        Message::ServerGreet(ServerGreet::default()),
+        Message::Ping(Ping { nonce: 1 }),
+        Message::ClientGreet(ClientGreet::new(2)),
+        Message::Pong(Pong { nonce: 1 }),
+        Message::AddRecord(
+            AddRecord::new(100, AddRecordType::Record, "ai".into(), "DEV:AI".into()).unwrap(),
+        ),
+        Message::DelRecord(DelRecord { recid: 100 }),
+        Message::UploadDone(UploadDone),
+        Message::AddInfo(AddInfo::new(100, "key".into(), "value".into()).unwrap()),
+        Message::Unknown {
+            msg_id: 0x8010,
+            body: vec![0xff],
+        },
+    ];
+    let json = serde_json::to_string(&messages).unwrap();
+    let decoded: Vec<Message> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, messages);
+}
+
+#[test]
+fn announcement_and_header_round_trip_through_json() {
+    let ann = Announcement::new(Ipv4Addr::new(10, 0, 0, 1), 5050, 7);
+    let json = serde_json::to_value(&ann).unwrap();
+    assert_eq!(json["server_addr"], "10.0.0.1");
+    assert_eq!(serde_json::from_value::<Announcement>(json).unwrap(), ann);
+
+    let header = MessageHeader::new(0x0003, 12);
+    let json = serde_json::to_string(&header).unwrap();
+    assert_eq!(
+        serde_json::from_str::<MessageHeader>(&json).unwrap(),
+        header
+    );
+}
@@ -0,0 +1,112 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use std::{
+    io::{Cursor, ErrorKind},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+use wire::{
+    sync::{MessageReader, MessageWriter},
+    AddInfo, ClientGreet, ClientMessage, Error, Message, MessageHeader, Ping, Pong, ServerGreet,
+    ServerMessage, UploadDone,
+};
+
+#[test]
+fn writer_output_reads_back() {
+    let messages = vec![
+        Message::ClientGreet(ClientGreet::new(1)),
+        Message::AddInfo(AddInfo::new(0, "ENGINEER".into(), "me".into()).unwrap()),
+        Message::UploadDone(UploadDone),
+    ];
+    let mut writer = MessageWriter::new(Vec::new());
+    for msg in &messages {
+        writer.write_message(msg).unwrap();
+    }
+
+    let reader = MessageReader::new(Cursor::new(writer.into_inner()));
+    let decoded: Vec<Message> = reader.map(Result::unwrap).collect();
+    assert_eq!(decoded, messages);
+}
+
+#[test]
+fn end_of_stream_inside_a_frame_is_an_error() {
+    let mut writer = MessageWriter::new(Vec::new());
+    writer
+        .write_message(&Message::Ping(Ping { nonce: 1 }))
+        .unwrap();
+    let mut data = writer.into_inner();
+
+    for len in [3, data.len() - 1] {
+        let mut reader = MessageReader::new(&data[..len]);
+        match reader.read_message() {
+            Err(Error::Io(err)) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    data.truncate(0);
+    assert!(MessageReader::new(&data[..])
+        .read_message()
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn oversized_frame_is_rejected_before_its_body_is_read() {
+    let header = MessageHeader::new(0x0006, 1 << 30).as_bytes();
+    let mut reader = MessageReader::new(Cursor::new(header.to_vec()));
+    assert!(matches!(
+        reader.read_message(),
+        Err(Error::FrameTooLarge { .. })
+    ));
+    // Only the header was consumed
+    assert_eq!(reader.get_ref().position(), 8);
+}
+
+#[test]
+fn blocking_handshake_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = MessageReader::new(stream.try_clone().unwrap());
+        let mut writer = MessageWriter::new(stream);
+        writer
+            .write_message(&ServerMessage::ServerGreet(ServerGreet::default()).into())
+            .unwrap();
+        writer
+            .write_message(&ServerMessage::Ping(Ping { nonce: 42 }).into())
+            .unwrap();
+        let greet = ClientMessage::try_from(reader.read_message().unwrap().unwrap()).unwrap();
+        let pong = ClientMessage::try_from(reader.read_message().unwrap().unwrap()).unwrap();
+        (greet, pong)
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut reader = MessageReader::new(stream.try_clone().unwrap());
+    let mut writer = MessageWriter::new(stream);
+    assert_eq!(
+        reader.read_message().unwrap(),
+        Some(Message::ServerGreet(ServerGreet::default()))
+    );
+    writer
+        .write_message(&Message::ClientGreet(ClientGreet::new(7)))
+        .unwrap();
+    match reader.read_message().unwrap() {
+        Some(Message::Ping(ping)) => writer
+            .write_message(&Message::Pong(Pong { nonce: ping.nonce }))
+            .unwrap(),
+        other => panic!("unexpected message {:?}", other),
+    }
+
+    let (greet, pong) = server.join().unwrap();
+    assert_eq!(greet, ClientMessage::ClientGreet(ClientGreet::new(7)));
+    assert_eq!(pong, ClientMessage::Pong(Pong { nonce: 42 }));
+}
@@ -0,0 +1,57 @@
+// This file is part of Recsync-rs.
+// Copyright (c) 2024 UK Research and Innovation, Science and Technology Facilities Council
+//
+// This project is licensed under both the MIT License and the BSD 3-Clause License.
+// You must comply with both licenses to use, modify, or distribute this software.
+// See the LICENSE file for details.
+
+#![allow(missing_docs)]
+use wire::{
+    decode_ref_from, encode_into, AddInfo, AddRecord, AddRecordType, Error, Message, MessageRef,
+};
+
+#[test]
+fn add_record_view_borrows_from_frame() {
+    let record = AddRecord::new(100, AddRecordType::Alias, "ai".into(), "DEV:X".into()).unwrap();
+    let mut buf = Vec::new();
+    encode_into(&Message::AddRecord(record.clone()), &mut buf).unwrap();
+
+    let (msg, len) = decode_ref_from(&buf).unwrap().unwrap();
+    assert_eq!(len, buf.len());
+    let view = match msg {
+        MessageRef::AddRecord(view) => view,
+        other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(view.recid, 100);
+    assert_eq!(view.rtype().unwrap(), "ai");
+    assert_eq!(view.rname().unwrap(), "DEV:X");
+    assert!(std::ptr::eq(
+        view.rname_bytes().as_ptr(),
+        &buf[buf.len() - 5]
+    ));
+    assert_eq!(view.into_owned().unwrap(), record);
+}
+
+#[test]
+fn add_info_view_validates_utf8_lazily() {
+    let mut buf = Vec::new();
+    encode_into(
+        &Message::AddInfo(AddInfo::new(7, "key".into(), "value".into()).unwrap()),
+        &mut buf,
+    )
+    .unwrap();
+    let last = buf.len() - 1;
+    buf[last] = 0xff;
+
+    let (msg, _) = decode_ref_from(&buf).unwrap().unwrap();
+    let view = match msg {
+        MessageRef::AddInfo(view) => view,
+        other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(view.key().unwrap(), "key");
+    assert!(matches!(view.value(), Err(Error::InvalidUtf8(_))));
+    assert!(matches!(
+        MessageRef::AddInfo(view).into_owned(),
+        Err(Error::InvalidUtf8(_))
+    ));
+}